@@ -0,0 +1,360 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+/* Syscall layer for the `mock` feature: the same functions as arch/x86.rs,
+ * with every trap going to the simulated kernel in mock.rs. MR0 and MR1
+ * travel through the IPC buffer, since there are no registers to put them
+ * in. */
+
+macro_rules! opt_deref {
+    ($name:expr) => {
+        if !$name.is_null() {
+            *$name
+        } else {
+            0
+        }
+    }
+}
+
+macro_rules! opt_assign {
+    ($loc:expr, $val:expr) => {
+        if !$loc.is_null() {
+            *$loc = $val;
+        }
+    }
+}
+
+#[inline(always)]
+pub unsafe fn seL4_GetTag() -> seL4_MessageInfo {
+    seL4_MessageInfo { words: (*seL4_GetIPCBuffer()).tag.words }
+}
+
+#[inline(always)]
+pub unsafe fn seL4_SetTag(tag: seL4_MessageInfo) {
+    (*seL4_GetIPCBuffer()).tag = tag;
+}
+
+#[inline(always)]
+pub unsafe fn seL4_GetMR(regnum: isize) -> seL4_Word {
+    (*seL4_GetIPCBuffer()).msg[regnum as usize]
+}
+
+#[inline(always)]
+pub unsafe fn seL4_SetMR(regnum: isize, value: seL4_Word) {
+    (*seL4_GetIPCBuffer()).msg[regnum as usize] = value;
+}
+
+#[inline(always)]
+pub unsafe fn seL4_GetUserData() -> seL4_Word {
+    (*seL4_GetIPCBuffer()).userData
+}
+
+#[inline(always)]
+pub unsafe fn seL4_GetIPCBuffer() -> *mut seL4_IPCBuffer {
+    mock::ipc_buffer()
+}
+
+#[inline(always)]
+pub unsafe fn seL4_SetUserData(data: seL4_Word) {
+    (*seL4_GetIPCBuffer()).userData = data;
+}
+
+#[inline(always)]
+pub unsafe fn seL4_GetBadge(index: isize) -> seL4_CapData {
+    seL4_CapData { words: [(*seL4_GetIPCBuffer()).caps_or_badges[index as usize]] }
+}
+
+#[inline(always)]
+pub unsafe fn seL4_GetCap(index: isize) -> seL4_CPtr {
+    (*seL4_GetIPCBuffer()).caps_or_badges[index as usize]
+}
+
+#[inline(always)]
+pub unsafe fn seL4_SetCap(index: isize, cptr: seL4_CPtr) {
+    (*seL4_GetIPCBuffer()).caps_or_badges[index as usize] = cptr;
+}
+
+#[inline(always)]
+pub unsafe fn seL4_GetCapReceivePath(receiveCNode: *mut seL4_CPtr,
+                                     receiveIndex: *mut seL4_CPtr,
+                                     receiveDepth: *mut seL4_Word) {
+    let buffer = &*seL4_GetIPCBuffer();
+    opt_assign!(receiveCNode, buffer.receiveCNode);
+    opt_assign!(receiveIndex, buffer.receiveIndex);
+    opt_assign!(receiveDepth, buffer.receiveDepth);
+}
+
+#[inline(always)]
+pub unsafe fn seL4_SetCapReceivePath(receiveCNode: seL4_CPtr,
+                                     receiveIndex: seL4_CPtr,
+                                     receiveDepth: seL4_Word) {
+    let buffer = &mut *seL4_GetIPCBuffer();
+    buffer.receiveCNode = receiveCNode;
+    buffer.receiveIndex = receiveIndex;
+    buffer.receiveDepth = receiveDepth;
+}
+
+/* Put the register MRs where the mock kernel reads them. */
+unsafe fn load_mrs(msgInfo: &seL4_MessageInfo, mr0: *mut seL4_Word, mr1: *mut seL4_Word) {
+    if msgInfo.get_length() > 0 {
+        seL4_SetMR(0, opt_deref!(mr0));
+    }
+    if msgInfo.get_length() > 1 {
+        seL4_SetMR(1, opt_deref!(mr1));
+    }
+}
+
+/* Hand the received register MRs back to the caller. */
+unsafe fn store_mrs(mr0: *mut seL4_Word, mr1: *mut seL4_Word) {
+    opt_assign!(mr0, seL4_GetMR(0));
+    opt_assign!(mr1, seL4_GetMR(1));
+}
+
+/* The result the replay script has for this syscall, or None when the
+ * thread is not replaying and the model should handle it. */
+macro_rules! scripted {
+    ($syscall:ident, $dest:expr) => {
+        ::replay::step(SyscallId::$syscall, $dest, &seL4_MessageInfo::new(0, 0, 0, 0), 0, 0)
+    };
+    ($syscall:ident, $dest:expr, $info:expr, $mr0:expr, $mr1:expr) => {
+        ::replay::step(SyscallId::$syscall, $dest, &$info, $mr0, $mr1)
+    };
+}
+
+#[inline(always)]
+pub unsafe fn seL4_Send(dest: seL4_CPtr, msgInfo: seL4_MessageInfo) {
+    let trace = trace_start!(Send, dest, msgInfo, seL4_GetMR(0), seL4_GetMR(1));
+    if scripted!(Send, dest, msgInfo, seL4_GetMR(0), seL4_GetMR(1)).is_none() {
+        mock::send(dest, msgInfo, true);
+    }
+    trace_finish!(trace);
+}
+
+#[inline(always)]
+pub unsafe fn seL4_SendWithMRs(dest: seL4_CPtr, msgInfo: seL4_MessageInfo,
+                               mr0: *mut seL4_Word, mr1: *mut seL4_Word) {
+    load_mrs(&msgInfo, mr0, mr1);
+    seL4_Send(dest, msgInfo);
+}
+
+#[inline(always)]
+pub unsafe fn seL4_NBSend(dest: seL4_CPtr, msgInfo: seL4_MessageInfo) {
+    let trace = trace_start!(NBSend, dest, msgInfo, seL4_GetMR(0), seL4_GetMR(1));
+    if scripted!(NBSend, dest, msgInfo, seL4_GetMR(0), seL4_GetMR(1)).is_none() {
+        mock::send(dest, msgInfo, false);
+    }
+    trace_finish!(trace);
+}
+
+#[inline(always)]
+pub unsafe fn seL4_NBSendWithMRs(dest: seL4_CPtr, msgInfo: seL4_MessageInfo,
+                                 mr0: *mut seL4_Word, mr1: *mut seL4_Word) {
+    load_mrs(&msgInfo, mr0, mr1);
+    seL4_NBSend(dest, msgInfo);
+}
+
+#[inline(always)]
+pub unsafe fn seL4_Reply(msgInfo: seL4_MessageInfo) {
+    let trace = trace_start!(Reply, 0, msgInfo, seL4_GetMR(0), seL4_GetMR(1));
+    if scripted!(Reply, 0, msgInfo, seL4_GetMR(0), seL4_GetMR(1)).is_none() {
+        mock::reply(msgInfo);
+    }
+    trace_finish!(trace);
+}
+
+#[inline(always)]
+pub unsafe fn seL4_ReplyWithMRs(msgInfo: seL4_MessageInfo,
+                                mr0: *mut seL4_Word, mr1: *mut seL4_Word) {
+    load_mrs(&msgInfo, mr0, mr1);
+    seL4_Reply(msgInfo);
+}
+
+#[inline(always)]
+pub unsafe fn seL4_Notify(dest: seL4_CPtr, msg: seL4_Word) {
+    let info = seL4_MessageInfo::new(0, 0, 0, 1);
+    let trace = trace_start!(Send, dest, info, msg, 0);
+    if scripted!(Send, dest, info, msg, 0).is_none() {
+        mock::notify(dest, msg);
+    }
+    trace_finish!(trace);
+}
+
+#[inline(always)]
+pub unsafe fn seL4_Wait(src: seL4_CPtr, sender: *mut seL4_Word) -> seL4_MessageInfo {
+    let trace = trace_start!(Wait, src);
+    let (info, badge) = match scripted!(Wait, src) {
+        Some(outcome) => replay::received(outcome),
+        None => mock::wait(src),
+    };
+    trace_finish!(trace, info, badge, seL4_GetMR(0), seL4_GetMR(1));
+    opt_assign!(sender, badge);
+    info
+}
+
+#[inline(always)]
+pub unsafe fn seL4_WaitWithMRs(src: seL4_CPtr, sender: *mut seL4_Word,
+                               mr0: *mut seL4_Word, mr1: *mut seL4_Word) -> seL4_MessageInfo {
+    let info = seL4_Wait(src, sender);
+    store_mrs(mr0, mr1);
+    info
+}
+
+#[inline(always)]
+pub unsafe fn seL4_Call(dest: seL4_CPtr, msgInfo: seL4_MessageInfo) -> seL4_MessageInfo {
+    let trace = trace_start!(Call, dest, msgInfo, seL4_GetMR(0), seL4_GetMR(1));
+    let info = match scripted!(Call, dest, msgInfo, seL4_GetMR(0), seL4_GetMR(1)) {
+        Some(outcome) => replay::received(outcome).0,
+        None => mock::call(dest, msgInfo),
+    };
+    trace_finish!(trace, info, 0, seL4_GetMR(0), seL4_GetMR(1));
+    info
+}
+
+#[inline(always)]
+pub unsafe fn seL4_CallWithMRs(dest: seL4_CPtr, msgInfo: seL4_MessageInfo,
+                               mr0: *mut seL4_Word, mr1: *mut seL4_Word) -> seL4_MessageInfo {
+    load_mrs(&msgInfo, mr0, mr1);
+    let info = seL4_Call(dest, msgInfo);
+    store_mrs(mr0, mr1);
+    info
+}
+
+#[inline(always)]
+pub unsafe fn seL4_ReplyWait(dest: seL4_CPtr, msgInfo: seL4_MessageInfo,
+                             sender: *mut seL4_Word) -> seL4_MessageInfo {
+    let trace = trace_start!(ReplyWait, dest, msgInfo, seL4_GetMR(0), seL4_GetMR(1));
+    let (info, badge) = match scripted!(ReplyWait, dest, msgInfo, seL4_GetMR(0), seL4_GetMR(1)) {
+        Some(outcome) => replay::received(outcome),
+        None => {
+            mock::reply(msgInfo);
+            mock::wait(dest)
+        }
+    };
+    trace_finish!(trace, info, badge, seL4_GetMR(0), seL4_GetMR(1));
+    opt_assign!(sender, badge);
+    info
+}
+
+#[inline(always)]
+pub unsafe fn seL4_ReplyWaitWithMRs(dest: seL4_CPtr, msgInfo: seL4_MessageInfo, sender: *mut seL4_Word,
+                                    mr0: *mut seL4_Word, mr1: *mut seL4_Word) -> seL4_MessageInfo {
+    load_mrs(&msgInfo, mr0, mr1);
+    let info = seL4_ReplyWait(dest, msgInfo, sender);
+    store_mrs(mr0, mr1);
+    info
+}
+
+/// The old, misspelt name of `seL4_ReplyWaitWithMRs`.
+#[deprecated(note = "renamed to seL4_ReplyWaitWithMRs")]
+#[inline(always)]
+pub unsafe fn seL4_ReplayWaitWithMRs(dest: seL4_CPtr, msgInfo: seL4_MessageInfo, sender: *mut seL4_Word,
+                                     mr0: *mut seL4_Word, mr1: *mut seL4_Word) -> seL4_MessageInfo {
+    seL4_ReplyWaitWithMRs(dest, msgInfo, sender, mr0, mr1)
+}
+
+#[inline(always)]
+pub unsafe fn seL4_Yield() {
+    let trace = trace_start!(Yield, 0);
+    if scripted!(Yield, 0).is_none() {
+        mock::yield_now();
+    }
+    trace_finish!(trace);
+}
+
+#[inline(always)]
+#[cfg(feature = "SEL4_DEBUG")]
+pub unsafe fn seL4_DebugPutChar(c: u8) {
+    mock::debug_put_char(c);
+}
+
+#[inline(always)]
+#[cfg(feature = "SEL4_DEBUG")]
+pub unsafe fn seL4_DebugHalt() {
+    let trace = trace_start!(DebugHalt, 0);
+    scripted!(DebugHalt, 0);
+    /* The kernel would not return, but the trace should not show a
+     * syscall left hanging. */
+    trace_finish!(trace);
+    panic!("seL4_DebugHalt");
+}
+
+#[inline(always)]
+#[cfg(feature = "SEL4_DEBUG")]
+pub unsafe fn seL4_DebugSnapshot() {
+    let trace = trace_start!(DebugSnapshot, 0);
+    scripted!(DebugSnapshot, 0);
+    trace_finish!(trace);
+}
+
+#[inline(always)]
+#[cfg(feature = "SEL4_DEBUG")]
+pub unsafe fn seL4_DebugCapIdentify(cap: seL4_CPtr) -> u32 {
+    let trace = trace_start!(DebugCapIdentify, cap);
+    let tag = match scripted!(DebugCapIdentify, cap) {
+        Some(outcome) => replay::value(outcome) as u32,
+        None => mock::cap_identify(cap),
+    };
+    trace_finish!(trace, tag);
+    tag
+}
+
+// Note: name is truncated to seL4_MaxThreadNameLength bytes and NUL-terminated
+// in the IPC buffer, so it need not be NUL-terminated itself.
+#[inline(always)]
+#[cfg(feature = "SEL4_DEBUG")]
+pub unsafe fn seL4_DebugNameThread(tcb: seL4_CPtr, name: &[u8]) {
+    let len = ::core::cmp::min(name.len(), seL4_MaxThreadNameLength);
+    let dest = (*seL4_GetIPCBuffer()).msg.as_mut_ptr() as *mut u8;
+    ::core::ptr::copy_nonoverlapping(name.as_ptr(), dest, len);
+    *dest.offset(len as isize) = 0;
+    let trace = trace_start!(DebugNameThread, tcb);
+    if scripted!(DebugNameThread, tcb).is_none() {
+        mock::name_thread(tcb);
+    }
+    trace_finish!(trace);
+}
+
+#[inline(always)]
+#[cfg(feature = "SEL4_DANGEROUS_CODE_INJECTION")]
+pub unsafe fn seL4_DebugRun(userfn: extern fn(*mut u8), userarg: *mut u8) {
+    let trace = trace_start!(DebugRun, 0);
+    scripted!(DebugRun, 0);
+    userfn(userarg);
+    trace_finish!(trace);
+}
+
+/* The mock kernel keeps no benchmark log; a replay gets the values from the
+ * traced run. */
+
+#[inline(always)]
+#[cfg(feature = "SEL4_CONFIG_BENCHMARK")]
+pub unsafe fn seL4_BenchmarkResetLog() {
+    let trace = trace_start!(BenchmarkResetLog, 0);
+    scripted!(BenchmarkResetLog, 0);
+    trace_finish!(trace);
+}
+
+#[inline(always)]
+#[cfg(feature = "SEL4_CONFIG_BENCHMARK")]
+pub unsafe fn seL4_BenchmarkDumpLog(_start: seL4_Word, _size: seL4_Word) -> u32 {
+    let trace = trace_start!(BenchmarkDumpLog, 0);
+    let dump = scripted!(BenchmarkDumpLog, 0).map_or(0, replay::value) as u32;
+    trace_finish!(trace, dump);
+    dump
+}
+
+#[inline(always)]
+#[cfg(feature = "SEL4_CONFIG_BENCHMARK")]
+pub unsafe fn seL4_BenchmarkLogSize() -> u32 {
+    let trace = trace_start!(BenchmarkLogSize, 0);
+    let size = scripted!(BenchmarkLogSize, 0).map_or(0, replay::value) as u32;
+    trace_finish!(trace, size);
+    size
+}
@@ -9,100 +9,52 @@
  * @TAG(NICTA_BSD)
  */
 
-pub type seL4_Word = u32;
-pub type seL4_CPtr = seL4_Word;
-
-pub type seL4_IA32_ASIDControl = seL4_CPtr;
-pub type seL4_IA32_ASIDPool = seL4_CPtr;
-pub type seL4_IA32_IOSpace = seL4_CPtr;
-pub type seL4_IA32_IOPort = seL4_CPtr;
-pub type seL4_IA32_Page = seL4_CPtr;
-pub type seL4_IA32_PageDirectory = seL4_CPtr;
-pub type seL4_IA32_PageTable = seL4_CPtr;
-pub type seL4_IA32_IOPageTable = seL4_CPtr;
-
-pub type seL4_CNode = seL4_CPtr;
-pub type seL4_IRQHandler = seL4_CPtr;
-pub type seL4_IRQControl = seL4_CPtr;
-pub type seL4_TCB = seL4_CPtr;
-pub type seL4_Untyped = seL4_CPtr;
-pub type seL4_DomainSet = seL4_CPtr;
-
-pub struct seL4_UserContext {
-    pub eip: seL4_Word,
-    pub esp: seL4_Word,
-    pub eflags: seL4_Word,
-    pub eax: seL4_Word,
-    pub ebx: seL4_Word,
-    pub ecx: seL4_Word,
-    pub edx: seL4_Word,
-    pub esi: seL4_Word,
-    pub edi: seL4_Word,
-    pub ebp: seL4_Word,
-    pub tls_base: seL4_Word,
-    pub fs: seL4_Word,
-    pub gs: seL4_Word,
-}
-
-pub const seL4_MsgLengthBits: usize = 7;
-pub const seL4_MsgMaxLength: usize = 120;
-pub const seL4_MsgExtraCapBits: usize = 2;
-pub const seL4_MsgMaxExtraCaps: usize = (1usize << seL4_MsgExtraCapBits) - 1;
-
-pub struct seL4_IPCBuffer {
-    pub tag: seL4_MessageInfo,
-    pub msg: [seL4_Word; seL4_MsgMaxLength],
-    pub userData: seL4_Word,
-    pub caps_or_badges: [seL4_Word; seL4_MsgMaxExtraCaps],
-    pub receiveCNode: seL4_CPtr,
-    pub receiveIndex: seL4_CPtr,
-    pub receiveDepth: seL4_CPtr,
-}
-
-pub const Default_VMAttributes: usize = 0;
-pub enum seL4_IA32_VMAttributes {
-    WriteBack = 0,
-    WriteThrough = 1,
-    CacheDisabled = 2,
-    Uncacheable = 3,
-    WriteCombining = 4,
-}
-
-pub enum seL4_CapRights {
-    CanWrite = 0x01,
-    CanRead = 0x02,
-    CanGrant = 0x04,
-    AllRights = 0x07,
+/* The segment register the accessors below reach the IPC buffer through
+ * (see tls.rs). Field offsets are immediates, printed bare by ${N:c}. */
+#[cfg(not(feature = "tls"))]
+macro_rules! ipc_buffer_seg {
+    () => { "%gs" }
+}
+
+#[cfg(feature = "tls")]
+macro_rules! ipc_buffer_seg {
+    () => { "%fs" }
 }
 
 #[inline(always)]
 pub unsafe fn seL4_GetTag() -> seL4_MessageInfo {
     let mut tag: seL4_MessageInfo = ::core::mem::uninitialized();
-    asm!("movl %gs:0, $0" : "=r"(tag.words[0]) : : : "volatile");
+    asm!(concat!("movl ", ipc_buffer_seg!(), ":${1:c}, $0")
+         : "=r"(tag.words[0]) : "i"(IPC_BUFFER_TAG) : : "volatile");
     tag
 }
 
 #[inline(always)]
 pub unsafe fn seL4_SetTag(tag: seL4_MessageInfo) {
-    asm!("movl $0, %gs:0" : : "r"(tag.words[0]) : "memory" : "volatile");
+    asm!(concat!("movl $0, ", ipc_buffer_seg!(), ":${1:c}")
+         : : "r"(tag.words[0]), "i"(IPC_BUFFER_TAG) : "memory" : "volatile");
 }
 
 #[inline(always)]
 pub unsafe fn seL4_GetMR(regnum: isize) -> seL4_Word {
     let mr;
-    asm!("movl %gs:4(,$1,0x4), $0" : "=r"(mr) : "r"(regnum) : : "volatile");
+    asm!(concat!("movl ", ipc_buffer_seg!(), ":${1:c}(,$2,0x4), $0")
+         : "=r"(mr) : "i"(IPC_BUFFER_MSG), "r"(regnum) : : "volatile");
     mr
 }
 
 #[inline(always)]
 pub unsafe fn seL4_SetMR(regnum: isize, value: seL4_Word) {
-    asm!("movl $0, %gs:4(,$1,0x4)" : : "r"(value), "r"(regnum) : "memory" : "volatile");
+    asm!(concat!("movl $0, ", ipc_buffer_seg!(), ":${1:c}(,$2,0x4)")
+         : : "r"(value), "i"(IPC_BUFFER_MSG), "r"(regnum)
+         : "memory" : "volatile");
 }
 
 #[inline(always)]
 pub unsafe fn seL4_GetUserData() -> seL4_Word {
     let data;
-    asm!("movl %gs:484, $0" : "=r"(data) : : : "volatile");
+    asm!(concat!("movl ", ipc_buffer_seg!(), ":${1:c}, $0")
+         : "=r"(data) : "i"(IPC_BUFFER_USER_DATA) : : "volatile");
     data
 }
 
@@ -113,26 +65,31 @@ pub unsafe fn seL4_GetIPCBuffer() -> *mut seL4_IPCBuffer {
 
 #[inline(always)]
 pub unsafe fn seL4_SetUserData(data: seL4_Word) {
-    asm!("movl $0, %gs:484" : : "r"(data) : "memory" : "volatile");
+    asm!(concat!("movl $0, ", ipc_buffer_seg!(), ":${1:c}")
+         : : "r"(data), "i"(IPC_BUFFER_USER_DATA) : "memory" : "volatile");
 }
 
 #[inline(always)]
 pub unsafe fn seL4_GetBadge(index: isize) -> seL4_CapData {
     let mut badge: seL4_CapData = ::core::mem::uninitialized();
-    asm!("movl %gs:488(,$1,0x4), $0" : "=r"(badge.words[0]) : "r"(index) : : "volatile");
+    asm!(concat!("movl ", ipc_buffer_seg!(), ":${1:c}(,$2,0x4), $0") : "=r"(badge.words[0])
+         : "i"(IPC_BUFFER_CAPS_OR_BADGES), "r"(index) : : "volatile");
     badge
 }
 
 #[inline(always)]
 pub unsafe fn seL4_GetCap(index: isize) -> seL4_CPtr {
     let cptr;
-    asm!("movl %gs:488(,$1,0x4), $0" : "=r"(cptr) : "r"(index) : : "volatile");
+    asm!(concat!("movl ", ipc_buffer_seg!(), ":${1:c}(,$2,0x4), $0") : "=r"(cptr)
+         : "i"(IPC_BUFFER_CAPS_OR_BADGES), "r"(index) : : "volatile");
     cptr
 }
 
 #[inline(always)]
 pub unsafe fn seL4_SetCap(index: isize, cptr: seL4_CPtr) {
-    asm!("movl $0, %gs:488(,$1,0x4)" : : "r"(cptr), "r"(index) : "memory" : "volatile");
+    asm!(concat!("movl $0, ", ipc_buffer_seg!(), ":${1:c}(,$2,0x4)")
+         : : "r"(cptr), "i"(IPC_BUFFER_CAPS_OR_BADGES), "r"(index)
+         : "memory" : "volatile");
 }
 
 #[inline(always)]
@@ -140,15 +97,18 @@ pub unsafe fn seL4_GetCapReceivePath(receiveCNode: *mut seL4_CPtr,
                                      receiveIndex: *mut seL4_CPtr,
                                      receiveDepth: *mut seL4_Word) {
     if !receiveCNode.is_null() {
-        asm!("movl %gs:500, $0" : "=r"(*receiveCNode) : : : "volatile");
+        asm!(concat!("movl ", ipc_buffer_seg!(), ":${1:c}, $0")
+             : "=r"(*receiveCNode) : "i"(IPC_BUFFER_RECEIVE_CNODE) : : "volatile");
     }
 
     if !receiveIndex.is_null() {
-        asm!("movl %gs:504, $0" : "=r"(*receiveIndex) : : : "volatile");
+        asm!(concat!("movl ", ipc_buffer_seg!(), ":${1:c}, $0")
+             : "=r"(*receiveIndex) : "i"(IPC_BUFFER_RECEIVE_INDEX) : : "volatile");
     }
 
     if !receiveDepth.is_null() {
-        asm!("movl %gs:508, $0" : "=r"(*receiveDepth) : : : "volatile");
+        asm!(concat!("movl ", ipc_buffer_seg!(), ":${1:c}, $0")
+             : "=r"(*receiveDepth) : "i"(IPC_BUFFER_RECEIVE_DEPTH) : : "volatile");
     }
 }
 
@@ -156,13 +116,17 @@ pub unsafe fn seL4_GetCapReceivePath(receiveCNode: *mut seL4_CPtr,
 pub unsafe fn seL4_SetCapReceivePath(receiveCNode: seL4_CPtr,
                                      receiveIndex: seL4_CPtr,
                                      receiveDepth: seL4_Word) {
-    asm!("movl $0, %gs:500" : : "r"(receiveCNode) : "memory" : "volatile");
-    asm!("movl $0, %gs:504" : : "r"(receiveIndex) : "memory" : "volatile");
-    asm!("movl $0, %gs:508" : : "r"(receiveDepth) : "memory" : "volatile");
+    asm!(concat!("movl $0, ", ipc_buffer_seg!(), ":${1:c}")
+         : : "r"(receiveCNode), "i"(IPC_BUFFER_RECEIVE_CNODE) : "memory" : "volatile");
+    asm!(concat!("movl $0, ", ipc_buffer_seg!(), ":${1:c}")
+         : : "r"(receiveIndex), "i"(IPC_BUFFER_RECEIVE_INDEX) : "memory" : "volatile");
+    asm!(concat!("movl $0, ", ipc_buffer_seg!(), ":${1:c}")
+         : : "r"(receiveDepth), "i"(IPC_BUFFER_RECEIVE_DEPTH) : "memory" : "volatile");
 }
 
 #[inline(always)]
 pub unsafe fn seL4_Send(dest: seL4_CPtr, msgInfo: seL4_MessageInfo) {
+    let trace = trace_start!(Send, dest, msgInfo, seL4_GetMR(0), seL4_GetMR(1));
     asm!("pushl %ebp
           movl %ecx, %ebp
           movl %esp, %ecx
@@ -178,6 +142,7 @@ pub unsafe fn seL4_Send(dest: seL4_CPtr, msgInfo: seL4_MessageInfo) {
           "{cx}" (seL4_GetMR(1))
           : "%edx"
         : "volatile");
+    trace_finish!(trace);
 }
 
 macro_rules! opt_deref {
@@ -201,6 +166,7 @@ macro_rules! opt_assign {
 #[inline(always)]
 pub unsafe fn seL4_SendWithMRs(dest: seL4_CPtr, msgInfo: seL4_MessageInfo,
                                mr0: *mut seL4_Word, mr1: *mut seL4_Word) {
+    let trace = trace_start!(Send, dest, msgInfo, opt_deref!(mr0), opt_deref!(mr1));
     asm!("pushl %ebp
           movl %ecx, %ebp
           movl %esp, %ecx
@@ -216,10 +182,12 @@ pub unsafe fn seL4_SendWithMRs(dest: seL4_CPtr, msgInfo: seL4_MessageInfo,
           "{cx}" (opt_deref!(mr1))
           : "%edx"
         : "volatile");
+    trace_finish!(trace);
 }
 
 #[inline(always)]
 pub unsafe fn seL4_NBSend(dest: seL4_CPtr, msgInfo: seL4_MessageInfo) {
+    let trace = trace_start!(NBSend, dest, msgInfo, seL4_GetMR(0), seL4_GetMR(1));
     asm!("pushl %ebp
           movl %ecx, %ebp
           movl %esp, %ecx
@@ -229,16 +197,18 @@ pub unsafe fn seL4_NBSend(dest: seL4_CPtr, msgInfo: seL4_MessageInfo) {
           popl %ebp"
         :
         : "{ax}" (SyscallId::NBSend as seL4_Word),
-        "{bx}" (dest)
+        "{bx}" (dest),
           "{si}" (msgInfo.words[0]),
           "{di}" (seL4_GetMR(0)),
           "{cx}" (seL4_GetMR(1))
           : "%edx"
         : "volatile");
+    trace_finish!(trace);
 }
 #[inline(always)]
 pub unsafe fn seL4_NBSendWithMRs(dest: seL4_CPtr, msgInfo: seL4_MessageInfo,
                                  mr0: *mut seL4_Word, mr1: *mut seL4_Word) {
+    let trace = trace_start!(NBSend, dest, msgInfo, opt_deref!(mr0), opt_deref!(mr1));
     asm!("pushl %ebp
           movl %ecx, %ebp
           movl %esp, %ecx
@@ -254,10 +224,12 @@ pub unsafe fn seL4_NBSendWithMRs(dest: seL4_CPtr, msgInfo: seL4_MessageInfo,
           "{cx}" (opt_deref!(mr1))
           : "%edx"
         : "volatile");
+    trace_finish!(trace);
 }
 
 #[inline(always)]
 pub unsafe fn seL4_Reply(msgInfo: seL4_MessageInfo) {
+    let trace = trace_start!(Reply, 0, msgInfo, seL4_GetMR(0), seL4_GetMR(1));
     asm!("pushl %ebp
           movl %ecx, %ebp
           movl %esp, %ecx
@@ -267,15 +239,17 @@ pub unsafe fn seL4_Reply(msgInfo: seL4_MessageInfo) {
           popl %ebp"
         :
         : "{ax}" (SyscallId::Reply as seL4_Word),
-        "{si}" (msgInfo.words[0])
+        "{si}" (msgInfo.words[0]),
           "{di}" (seL4_GetMR(0)),
           "{cx}" (seL4_GetMR(1))
         : "%ebx", "%edx"
         : "volatile");
+    trace_finish!(trace);
 }
 #[inline(always)]
 pub unsafe fn seL4_ReplyWithMRs(msgInfo: seL4_MessageInfo,
                                 mr0: *mut seL4_Word, mr1: *mut seL4_Word) {
+    let trace = trace_start!(Reply, 0, msgInfo, opt_deref!(mr0), opt_deref!(mr1));
     asm!("pushl %ebp
           movl %ecx, %ebp
           movl %esp, %ecx
@@ -286,15 +260,17 @@ pub unsafe fn seL4_ReplyWithMRs(msgInfo: seL4_MessageInfo,
         :
         : "{ax}" (SyscallId::Reply as seL4_Word),
         "{si}" (msgInfo.words[0]),
-          "{di}" (opt_deref!(mr0))
+          "{di}" (opt_deref!(mr0)),
           "{cx}" (opt_deref!(mr1))
         : "%ebx", "%edx"
         : "volatile");
+    trace_finish!(trace);
 }
 
 
 #[inline(always)]
 pub unsafe fn seL4_Notify(dest: seL4_CPtr, msg: seL4_Word) {
+    let trace = trace_start!(Send, dest, seL4_MessageInfo::new(0, 0, 0, 1), msg, 0);
     asm!("pushl %ebp
           movl %esp, %ecx
           leal 1f, %edx
@@ -308,6 +284,7 @@ pub unsafe fn seL4_Notify(dest: seL4_CPtr, msg: seL4_Word) {
           "{di}" (msg)
         : "%ecx", "%edx"
         : "volatile");
+    trace_finish!(trace);
 }
 
 #[inline(always)]
@@ -317,6 +294,7 @@ pub unsafe fn seL4_Wait(src: seL4_CPtr, sender: *mut seL4_Word) -> seL4_MessageI
     let mr0: seL4_Word;
     let mr1: seL4_Word;
 
+    let trace = trace_start!(Wait, src);
     asm!("pushl %ebp
           movl %esp, %ecx
           leal 1f, %edx
@@ -326,19 +304,20 @@ pub unsafe fn seL4_Wait(src: seL4_CPtr, sender: *mut seL4_Word) -> seL4_MessageI
           popl %ebp"
         : "={bx}" (badge),
           "={si}" (info.words[0]),
-          "={dx}" (mr0),
+          "={di}" (mr0),
           "={cx}" (mr1)
         : "{ax}" (SyscallId::Wait as seL4_Word),
         "{bx}" (src)
         : "%edx", "memory"
         : "volatile");
+    trace_finish!(trace, info, badge, mr0, mr1);
 
     seL4_SetMR(0, mr0);
     seL4_SetMR(1, mr1);
 
     opt_assign!(sender, badge);
 
-    ::core::mem::uninitialized()
+    info
 }
 
 #[inline(always)]
@@ -349,6 +328,7 @@ pub unsafe fn seL4_WaitWithMRs(src: seL4_CPtr, sender: *mut seL4_Word,
     let msg0: seL4_Word;
     let msg1: seL4_Word;
 
+    let trace = trace_start!(Wait, src);
     asm!("pushl %ebp
           movl %esp, %ecx
           leal 1f, %edx
@@ -364,6 +344,7 @@ pub unsafe fn seL4_WaitWithMRs(src: seL4_CPtr, sender: *mut seL4_Word,
         "{bx}" (src)
         : "%edx", "memory"
         : "volatile");
+    trace_finish!(trace, info, badge, msg0, msg1);
 
     opt_assign!(mr0, msg0);
     opt_assign!(mr1, msg1);
@@ -378,6 +359,7 @@ pub unsafe fn seL4_Call(mut dest: seL4_CPtr, msgInfo: seL4_MessageInfo) -> seL4_
     let mut mr0 = seL4_GetMR(0);
     let mut mr1 = seL4_GetMR(1);
 
+    let trace = trace_start!(Call, dest, msgInfo, mr0, mr1);
     asm!("pushl %ebp
           movl %ecx, %ebp
           movl %esp, %ecx
@@ -397,6 +379,7 @@ pub unsafe fn seL4_Call(mut dest: seL4_CPtr, msgInfo: seL4_MessageInfo) -> seL4_
           "{cx}" (mr1)
           : "%edx", "memory"
         : "volatile");
+    trace_finish!(trace, info, 0, mr0, mr1);
 
     seL4_SetMR(0, mr0);
     seL4_SetMR(1, mr1);
@@ -422,6 +405,7 @@ pub unsafe fn seL4_CallWithMRs(mut dest: seL4_CPtr, msgInfo: seL4_MessageInfo,
         }
     }
 
+    let trace = trace_start!(Call, dest, msgInfo, msg0, msg1);
     asm!("pushl %ebp
           movl %ecx, %ebp
           movl %esp, %ecx
@@ -430,17 +414,18 @@ pub unsafe fn seL4_CallWithMRs(mut dest: seL4_CPtr, msgInfo: seL4_MessageInfo,
           sysenter
           movl %ebp, %ecx
           popl %ebp"
-        : "={si}" (info.words[0])
+        : "={si}" (info.words[0]),
         "={di}" (msg0),
           "={cx}" (msg1),
           "={bx}" (dest) /* dummy, tells GCC that ebx is clobbered (check if still necessary) */
         : "{ax}" (SyscallId::Call as seL4_Word),
-        "{bx}" (dest)
+        "{bx}" (dest),
           "{si}" (msgInfo.words[0]),
           "{di}" (msg0),
           "{cx}" (msg1)
           : "%edx", "memory"
         : "volatile");
+    trace_finish!(trace, info, 0, msg0, msg1);
 
     opt_assign!(mr0, msg0);
     opt_assign!(mr1, msg1);
@@ -456,6 +441,7 @@ pub unsafe fn seL4_ReplyWait(dest: seL4_CPtr, msgInfo: seL4_MessageInfo,
     let mut mr0 = seL4_GetMR(0);
     let mut mr1 = seL4_GetMR(1);
 
+    let trace = trace_start!(ReplyWait, dest, msgInfo, mr0, mr1);
     asm!("pushl %ebp
           movl %ecx, %ebp
           movl %esp, %ecx
@@ -475,6 +461,7 @@ pub unsafe fn seL4_ReplyWait(dest: seL4_CPtr, msgInfo: seL4_MessageInfo,
           "{cx}" (mr1)
           : "%edx", "memory"
         : "volatile");
+    trace_finish!(trace, info, badge, mr0, mr1);
 
     seL4_SetMR(0, mr0);
     seL4_SetMR(1, mr1);
@@ -485,8 +472,8 @@ pub unsafe fn seL4_ReplyWait(dest: seL4_CPtr, msgInfo: seL4_MessageInfo,
 }
 
 #[inline(always)]
-pub unsafe fn seL4_ReplayWaitWithMRs(dest: seL4_CPtr, msgInfo: seL4_MessageInfo, sender: *mut seL4_Word,
-                                     mr0: *mut seL4_Word, mr1: *mut seL4_Word) -> seL4_MessageInfo {
+pub unsafe fn seL4_ReplyWaitWithMRs(dest: seL4_CPtr, msgInfo: seL4_MessageInfo, sender: *mut seL4_Word,
+                                    mr0: *mut seL4_Word, mr1: *mut seL4_Word) -> seL4_MessageInfo {
     let mut info: seL4_MessageInfo = ::core::mem::uninitialized();
     let badge: seL4_Word;
     let mut msg0: seL4_Word = 0;
@@ -503,6 +490,7 @@ pub unsafe fn seL4_ReplayWaitWithMRs(dest: seL4_CPtr, msgInfo: seL4_MessageInfo,
         }
     }
 
+    let trace = trace_start!(ReplyWait, dest, msgInfo, msg0, msg1);
     asm!("pushl %ebp
           movl %ecx, %ebp
           movl %esp, %ecx
@@ -515,12 +503,14 @@ pub unsafe fn seL4_ReplayWaitWithMRs(dest: seL4_CPtr, msgInfo: seL4_MessageInfo,
         "={si}" (info.words[0]),
           "={di}" (msg0),
           "={cx}" (msg1)
-        : "{bx}" (dest),
-        "{si}" (msgInfo.words[0]),
+        : "{ax}" (SyscallId::ReplyWait as seL4_Word),
+        "{bx}" (dest),
+          "{si}" (msgInfo.words[0]),
           "{di}" (msg0),
           "{cx}" (msg1)
         : "%edx", "memory"
         : "volatile");
+    trace_finish!(trace, info, badge, msg0, msg1);
 
     opt_assign!(mr0, msg0);
     opt_assign!(mr1, msg1);
@@ -529,8 +519,17 @@ pub unsafe fn seL4_ReplayWaitWithMRs(dest: seL4_CPtr, msgInfo: seL4_MessageInfo,
     info
 }
 
+/// The old, misspelt name of `seL4_ReplyWaitWithMRs`.
+#[deprecated(note = "renamed to seL4_ReplyWaitWithMRs")]
+#[inline(always)]
+pub unsafe fn seL4_ReplayWaitWithMRs(dest: seL4_CPtr, msgInfo: seL4_MessageInfo, sender: *mut seL4_Word,
+                                     mr0: *mut seL4_Word, mr1: *mut seL4_Word) -> seL4_MessageInfo {
+    seL4_ReplyWaitWithMRs(dest, msgInfo, sender, mr0, mr1)
+}
+
 #[inline(always)]
 pub unsafe fn seL4_Yield() {
+    let trace = trace_start!(Yield, 0);
     asm!("pushl %ebp
           movl %esp, %ecx
           leal 1f, %edx
@@ -541,6 +540,7 @@ pub unsafe fn seL4_Yield() {
         : "{ax}" (SyscallId::Yield as seL4_Word)
         : "%ebx", "%ecx", "%edx", "%esi", "%edi", "memory"
         : "volatile");
+    trace_finish!(trace);
 }
 
 #[inline(always)]
@@ -562,6 +562,7 @@ pub unsafe fn seL4_DebugPutChar(c: u8) {
 #[inline(always)]
 #[cfg(feature = "SEL4_DEBUG")]
 pub unsafe fn seL4_DebugHalt() {
+    let trace = trace_start!(DebugHalt, 0);
     asm!("pushl %ebp
           movl %esp, %ecx
           leal 1f, %edx
@@ -572,11 +573,13 @@ pub unsafe fn seL4_DebugHalt() {
         : "{ax}" (SyscallId::DebugHalt as seL4_Word)
         : "%ebx", "%ecx", "%edx", "%esi", "%edi", "memory"
         : "volatile");
+    trace_finish!(trace);
 }
 
 #[inline(always)]
 #[cfg(feature = "SEL4_DEBUG")]
 pub unsafe fn seL4_DebugSnapshot() {
+    let trace = trace_start!(DebugSnapshot, 0);
     asm!("pushl %ebp
           movl %esp, %ecx
           leal 1f, %edx
@@ -587,12 +590,14 @@ pub unsafe fn seL4_DebugSnapshot() {
         : "{ax}" (SyscallId::DebugSnapshot as seL4_Word)
         : "%ebx", "%ecx", "%edx", "%esi", "%edi", "memory"
         : "volatile");
+    trace_finish!(trace);
 }
 
 #[inline(always)]
 #[cfg(feature = "SEL4_DEBUG")]
 pub unsafe fn seL4_DebugCapIdentify(cap: seL4_CPtr) -> u32 {
     let mut _cap = cap;
+    let trace = trace_start!(DebugCapIdentify, cap);
     asm!("pushl %ebp
           movl %esp, %ecx
           leal 1f, %edx
@@ -604,14 +609,20 @@ pub unsafe fn seL4_DebugCapIdentify(cap: seL4_CPtr) -> u32 {
           "{bx}" (_cap)
           : "%ecx", "%edx", "%esi", "%edi", "memory"
         : "volatile");
+    trace_finish!(trace, _cap);
     _cap
 }
 
-// Note: name MUST be NUL-terminated.
+// Note: name is truncated to seL4_MaxThreadNameLength bytes and NUL-terminated
+// in the IPC buffer, so it need not be NUL-terminated itself.
 #[inline(always)]
 #[cfg(feature = "SEL4_DEBUG")]
 pub unsafe fn seL4_DebugNameThread(tcb: seL4_CPtr, name: &[u8]) {
-    core::ptr::copy_nonoverlapping(seL4_GetIPCBuffer() as *mut u8, name.as_ptr() as *mut u8, name.len());
+    let len = ::core::cmp::min(name.len(), seL4_MaxThreadNameLength);
+    let dest = (*seL4_GetIPCBuffer()).msg.as_mut_ptr() as *mut u8;
+    ::core::ptr::copy_nonoverlapping(name.as_ptr(), dest, len);
+    *dest.offset(len as isize) = 0;
+    let trace = trace_start!(DebugNameThread, tcb);
     asm!("pushl %ebp
           movl %esp, %ecx
           leal 1f, %edx
@@ -623,12 +634,14 @@ pub unsafe fn seL4_DebugNameThread(tcb: seL4_CPtr, name: &[u8]) {
         "{bx}" (tcb)
         : "%ecx", "%edx", "%esi", "%edi", "memory"
         : "volatile");
+    trace_finish!(trace);
 }
 
 #[inline(always)]
 #[cfg(feature = "SEL4_DANGEROUS_CODE_INJECTION")]
 pub unsafe fn seL4_DebugRun(userfn: extern fn(*mut u8), userarg: *mut u8) {
     let userfnptr = userfn as *mut ();
+    let trace = trace_start!(DebugRun, 0);
     asm!("pushl %ebp
           movl %esp, %ecx
           leal 1f, %edx
@@ -641,11 +654,13 @@ pub unsafe fn seL4_DebugRun(userfn: extern fn(*mut u8), userarg: *mut u8) {
           "{si}" (userarg)
           : "%ecx", "%edx", "%edi", "memory"
         : "volatile");
+    trace_finish!(trace);
 }
 
 #[inline(always)]
 #[cfg(feature = "SEL4_CONFIG_BENCHMARK")]
 pub unsafe fn seL4_BenchmarkResetLog() {
+    let trace = trace_start!(BenchmarkResetLog, 0);
     asm!("pushl %ebp
           movl %esp, %ecx
           leal 1f, %edx
@@ -656,12 +671,14 @@ pub unsafe fn seL4_BenchmarkResetLog() {
         : "{ax}" (SyscallId::BenchmarkResetLog as seL4_Word)
         : "%ecx", "%edx", "%edi", "memory"
         : "volatile");
+    trace_finish!(trace);
 }
 
 #[inline(always)]
 #[cfg(feature = "SEL4_CONFIG_BENCHMARK")]
 pub unsafe fn seL4_BenchmarkDumpLog(start: seL4_Word, size: seL4_Word) -> u32 {
     let dump: u32;
+    let trace = trace_start!(BenchmarkDumpLog, 0);
     asm!("pushl %ebp
           movl %esp, %ecx
           leal 1f, %edx
@@ -674,6 +691,7 @@ pub unsafe fn seL4_BenchmarkDumpLog(start: seL4_Word, size: seL4_Word) -> u32 {
           "{si}" (size)
         : "%ecx", "%edx", "%edi", "memory"
         : "volatile");
+    trace_finish!(trace, dump);
     dump
 }
 
@@ -681,6 +699,7 @@ pub unsafe fn seL4_BenchmarkDumpLog(start: seL4_Word, size: seL4_Word) -> u32 {
 #[cfg(feature = "SEL4_CONFIG_BENCHMARK")]
 pub unsafe fn seL4_BenchmarkLogSize() -> u32 {
     let ret: u32;
+    let trace = trace_start!(BenchmarkLogSize, 0);
     asm!("pushl %ebp
           movl %esp, %ecx
           leal 1f, %edx
@@ -691,5 +710,6 @@ pub unsafe fn seL4_BenchmarkLogSize() -> u32 {
         : "{ax}" (SyscallId::BenchmarkLogSize as seL4_Word)
         : "%ecx", "%edx", "%edi", "memory"
         : "volatile");
+    trace_finish!(trace, ret);
     ret
 }
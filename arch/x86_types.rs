@@ -0,0 +1,321 @@
+/*
+ * Copyright 2015, Corey Richardson
+ * Copyright 2014, NICTA
+ *
+ * This software may be distributed and modified according to the terms of
+ * the BSD 2-Clause license. Note that NO WARRANTY is provided.
+ * See "LICENSE_BSD2.txt" for details.
+ *
+ * @TAG(NICTA_BSD)
+ */
+
+pub type seL4_Word = u32;
+pub type seL4_CPtr = seL4_Word;
+
+pub type seL4_IA32_ASIDControl = seL4_CPtr;
+pub type seL4_IA32_ASIDPool = seL4_CPtr;
+pub type seL4_IA32_IOSpace = seL4_CPtr;
+pub type seL4_IA32_IOPort = seL4_CPtr;
+pub type seL4_IA32_Page = seL4_CPtr;
+pub type seL4_IA32_PageDirectory = seL4_CPtr;
+pub type seL4_IA32_PageTable = seL4_CPtr;
+pub type seL4_IA32_IOPageTable = seL4_CPtr;
+
+pub type seL4_CNode = seL4_CPtr;
+pub type seL4_IRQHandler = seL4_CPtr;
+pub type seL4_IRQControl = seL4_CPtr;
+pub type seL4_TCB = seL4_CPtr;
+pub type seL4_Untyped = seL4_CPtr;
+pub type seL4_DomainSet = seL4_CPtr;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct seL4_UserContext {
+    pub eip: seL4_Word,
+    pub esp: seL4_Word,
+    pub eflags: seL4_Word,
+    pub eax: seL4_Word,
+    pub ebx: seL4_Word,
+    pub ecx: seL4_Word,
+    pub edx: seL4_Word,
+    pub esi: seL4_Word,
+    pub edi: seL4_Word,
+    pub ebp: seL4_Word,
+    pub tls_base: seL4_Word,
+    pub fs: seL4_Word,
+    pub gs: seL4_Word,
+}
+
+/// Selector of the segment the kernel bases at a thread's `tls_base`.
+pub const seL4_TLSSelector: seL4_Word = (6 << 3) | 3;
+/// Selector of the segment the kernel bases at a thread's IPC buffer.
+pub const seL4_IPCBufferSelector: seL4_Word = (7 << 3) | 3;
+
+/// Number of words in `seL4_UserContext`, and the largest register count
+/// accepted by `seL4_TCB_ReadRegisters` and `seL4_TCB_WriteRegisters`.
+pub const seL4_UserContextWords: usize = 13;
+
+impl seL4_UserContext {
+    /// Initial context for a thread entering at `pc` with stack `sp`.
+    ///
+    /// %gs is on the IPC buffer segment and %fs on the TLS segment, or the
+    /// other way round with the `tls` feature (see `tls`).
+    pub fn new_thread(pc: seL4_Word, sp: seL4_Word) -> seL4_UserContext {
+        let (fs, gs) = if cfg!(feature = "tls") {
+            (seL4_IPCBufferSelector, seL4_TLSSelector)
+        } else {
+            (seL4_TLSSelector, seL4_IPCBufferSelector)
+        };
+        seL4_UserContext {
+            eip: pc,
+            esp: sp,
+            fs: fs,
+            gs: gs,
+            ..Default::default()
+        }
+    }
+
+    /// Set the thread pointer used for thread-locals.
+    pub fn set_tls_base(&mut self, tp: seL4_Word) {
+        self.tls_base = tp;
+    }
+
+    /// Registers in the order the kernel transfers them.
+    pub fn as_words(&self) -> &[seL4_Word; seL4_UserContextWords] {
+        unsafe { &*(self as *const seL4_UserContext as *const [seL4_Word; seL4_UserContextWords]) }
+    }
+
+    pub fn as_words_mut(&mut self) -> &mut [seL4_Word; seL4_UserContextWords] {
+        unsafe { &mut *(self as *mut seL4_UserContext as *mut [seL4_Word; seL4_UserContextWords]) }
+    }
+
+    pub fn get_pc(&self) -> seL4_Word {
+        self.eip
+    }
+
+    pub fn set_pc(&mut self, v: seL4_Word) {
+        self.eip = v;
+    }
+
+    pub fn get_sp(&self) -> seL4_Word {
+        self.esp
+    }
+
+    pub fn set_sp(&mut self, v: seL4_Word) {
+        self.esp = v;
+    }
+
+    /// Argument registers for starting a thread: eax, ebx, ecx and edx.
+    pub fn get_arg(&self, n: usize) -> seL4_Word {
+        match n {
+            0 => self.eax,
+            1 => self.ebx,
+            2 => self.ecx,
+            3 => self.edx,
+            _ => panic!("no argument register {}", n),
+        }
+    }
+
+    pub fn set_arg(&mut self, n: usize, v: seL4_Word) {
+        match n {
+            0 => self.eax = v,
+            1 => self.ebx = v,
+            2 => self.ecx = v,
+            3 => self.edx = v,
+            _ => panic!("no argument register {}", n),
+        }
+    }
+}
+
+pub const seL4_MsgLengthBits: usize = 7;
+pub const seL4_MsgMaxLength: usize = 120;
+pub const seL4_MsgExtraCapBits: usize = 2;
+pub const seL4_MsgMaxExtraCaps: usize = (1usize << seL4_MsgExtraCapBits) - 1;
+
+/// Size of the IPC buffer, as a power of two. The buffer must be aligned to
+/// its size.
+pub const seL4_IPCBufferSizeBits: usize = 9;
+
+/// The IPC buffer, laid out as the kernel expects it.
+#[repr(C)]
+pub struct seL4_IPCBuffer {
+    pub tag: seL4_MessageInfo,
+    pub msg: [seL4_Word; seL4_MsgMaxLength],
+    pub userData: seL4_Word,
+    pub caps_or_badges: [seL4_Word; seL4_MsgMaxExtraCaps],
+    pub receiveCNode: seL4_CPtr,
+    pub receiveIndex: seL4_CPtr,
+    pub receiveDepth: seL4_CPtr,
+}
+
+/* Byte offsets of the fields of seL4_IPCBuffer, for the accessors' inline
+ * assembly, which needs them as constants. Words are 4 bytes on ia32;
+ * tests/layout.rs checks these against the struct. */
+#[doc(hidden)]
+pub const IPC_BUFFER_TAG: usize = 0;
+#[doc(hidden)]
+pub const IPC_BUFFER_MSG: usize = IPC_BUFFER_TAG + 4;
+#[doc(hidden)]
+pub const IPC_BUFFER_USER_DATA: usize = IPC_BUFFER_MSG + 4 * seL4_MsgMaxLength;
+#[doc(hidden)]
+pub const IPC_BUFFER_CAPS_OR_BADGES: usize = IPC_BUFFER_USER_DATA + 4;
+#[doc(hidden)]
+pub const IPC_BUFFER_RECEIVE_CNODE: usize = IPC_BUFFER_CAPS_OR_BADGES + 4 * seL4_MsgMaxExtraCaps;
+#[doc(hidden)]
+pub const IPC_BUFFER_RECEIVE_INDEX: usize = IPC_BUFFER_RECEIVE_CNODE + 4;
+#[doc(hidden)]
+pub const IPC_BUFFER_RECEIVE_DEPTH: usize = IPC_BUFFER_RECEIVE_INDEX + 4;
+
+#[allow(dead_code)]
+fn assert_ipc_buffer_size() {
+    /* The kernel reads and writes the whole of a 2^seL4_IPCBufferSizeBits
+     * buffer, so the struct must fill it exactly. */
+    let _: [u8; 1 << seL4_IPCBufferSizeBits] = unsafe { ::core::mem::transmute(::core::mem::zeroed::<seL4_IPCBuffer>()) };
+}
+
+pub enum seL4_Error {
+    seL4_NoError = 0,
+    seL4_InvalidArgument = 1,
+    seL4_InvalidCapability = 2,
+    seL4_IllegalOperation = 3,
+    seL4_RangeError = 4,
+    seL4_AlignmentError = 5,
+    seL4_FailedLookup = 6,
+    seL4_TruncatedMessage = 7,
+    seL4_DeleteFirst = 8,
+    seL4_RevokeFirst = 9,
+    seL4_NotEnoughMemory = 10,
+}
+
+pub const Default_VMAttributes: usize = 0;
+pub enum seL4_IA32_VMAttributes {
+    WriteBack = 0,
+    WriteThrough = 1,
+    CacheDisabled = 2,
+    Uncacheable = 3,
+    WriteCombining = 4,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum seL4_ObjectType {
+    seL4_UntypedObject = 0,
+    seL4_TCBObject = 1,
+    seL4_EndpointObject = 2,
+    seL4_AsyncEndpointObject = 3,
+    seL4_CapTableObject = 4,
+    seL4_IA32_4K = 5,
+    seL4_IA32_4M = 6,
+    seL4_IA32_PageTableObject = 7,
+    seL4_IA32_PageDirectoryObject = 8,
+}
+
+pub const seL4_PageBits: usize = 12;
+pub const seL4_SlotBits: usize = 4;
+pub const seL4_TCBBits: usize = 9;
+pub const seL4_EndpointBits: usize = 4;
+pub const seL4_PageTableBits: usize = 12;
+pub const seL4_PageDirBits: usize = 12;
+
+pub enum seL4_CapRights {
+    CanWrite = 0x01,
+    CanRead = 0x02,
+    CanGrant = 0x04,
+    AllRights = 0x07,
+}
+
+/// Kind of capability reported by `seL4_DebugCapIdentify`.
+///
+/// `from_tag` maps the tags of the kernel's `cap` tagged union, as
+/// generated from types.bf, to these.
+#[cfg(feature = "SEL4_DEBUG")]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum seL4_CapType {
+    NullCap,
+    UntypedCap,
+    EndpointCap,
+    NotificationCap,
+    ReplyCap,
+    CNodeCap,
+    ThreadCap,
+    IRQControlCap,
+    IRQHandlerCap,
+    ZombieCap,
+    DomainCap,
+    FrameCap,
+    PageTableCap,
+    PageDirectoryCap,
+    PDPTCap,
+    ASIDControlCap,
+    ASIDPoolCap,
+    IOPortCap,
+    IOSpaceCap,
+    IOPageTableCap,
+    Unknown(u32),
+}
+
+/* The kernel's tag for each kind of cap, from the `cap` tagged union that
+ * bitfield_gen.py generates out of types.bf, so a kernel update that renumbers
+ * them cannot go unnoticed. */
+#[cfg(feature = "SEL4_DEBUG")]
+const CAP_TAGS: [(u32, seL4_CapType); 20] = [
+    (capTag::cap_null_cap as u32, seL4_CapType::NullCap),
+    (capTag::cap_untyped_cap as u32, seL4_CapType::UntypedCap),
+    (capTag::cap_endpoint_cap as u32, seL4_CapType::EndpointCap),
+    (capTag::cap_async_endpoint_cap as u32, seL4_CapType::NotificationCap),
+    (capTag::cap_reply_cap as u32, seL4_CapType::ReplyCap),
+    (capTag::cap_cnode_cap as u32, seL4_CapType::CNodeCap),
+    (capTag::cap_thread_cap as u32, seL4_CapType::ThreadCap),
+    (capTag::cap_irq_control_cap as u32, seL4_CapType::IRQControlCap),
+    (capTag::cap_irq_handler_cap as u32, seL4_CapType::IRQHandlerCap),
+    (capTag::cap_zombie_cap as u32, seL4_CapType::ZombieCap),
+    (capTag::cap_domain_cap as u32, seL4_CapType::DomainCap),
+    (capTag::cap_frame_cap as u32, seL4_CapType::FrameCap),
+    (capTag::cap_page_table_cap as u32, seL4_CapType::PageTableCap),
+    (capTag::cap_page_directory_cap as u32, seL4_CapType::PageDirectoryCap),
+    (capTag::cap_pdpt_cap as u32, seL4_CapType::PDPTCap),
+    (capTag::cap_asid_control_cap as u32, seL4_CapType::ASIDControlCap),
+    (capTag::cap_asid_pool_cap as u32, seL4_CapType::ASIDPoolCap),
+    (capTag::cap_io_port_cap as u32, seL4_CapType::IOPortCap),
+    (capTag::cap_io_space_cap as u32, seL4_CapType::IOSpaceCap),
+    (capTag::cap_io_page_table_cap as u32, seL4_CapType::IOPageTableCap),
+];
+
+#[cfg(feature = "SEL4_DEBUG")]
+impl seL4_CapType {
+    pub fn from_tag(tag: u32) -> seL4_CapType {
+        CAP_TAGS.iter()
+                .find(|&&(t, _)| t == tag)
+                .map_or(seL4_CapType::Unknown(tag), |&(_, kind)| kind)
+    }
+
+    pub fn name(&self) -> &'static str {
+        use self::seL4_CapType::*;
+        match *self {
+            NullCap => "null",
+            UntypedCap => "untyped",
+            EndpointCap => "endpoint",
+            NotificationCap => "notification",
+            ReplyCap => "reply",
+            CNodeCap => "cnode",
+            ThreadCap => "thread",
+            IRQControlCap => "irq_control",
+            IRQHandlerCap => "irq_handler",
+            ZombieCap => "zombie",
+            DomainCap => "domain",
+            FrameCap => "frame",
+            PageTableCap => "page_table",
+            PageDirectoryCap => "page_directory",
+            PDPTCap => "pdpt",
+            ASIDControlCap => "asid_control",
+            ASIDPoolCap => "asid_pool",
+            IOPortCap => "io_port",
+            IOSpaceCap => "io_space",
+            IOPageTableCap => "io_page_table",
+            Unknown(_) => "unknown",
+        }
+    }
+}
+
+/// Longest thread name the kernel accepts, not counting the NUL terminator.
+#[cfg(feature = "SEL4_DEBUG")]
+pub const seL4_MaxThreadNameLength: usize = seL4_MsgMaxLength * 4 - 1;
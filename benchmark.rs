@@ -0,0 +1,97 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+//! Benchmarking support.
+//!
+//! Reading the log needs the `SEL4_CONFIG_BENCHMARK` feature; `host` builds
+//! get `LogEntry` and `intervals` for decoding a saved log. `Stats`, from
+//! the `stats` module, summarises the intervals.
+//!
+//! Benchmark kernels (`SEL4_CONFIG_BENCHMARK`) keep a log of words in kernel
+//! memory. Each record is two words: an event id followed by the low 32 bits
+//! of the cycle counter when the event happened. `seL4_BenchmarkDumpLog`
+//! copies part of the log into the IPC buffer's message registers, so the
+//! log is read out in chunks of at most `seL4_MsgMaxLength` words.
+
+#[cfg(all(feature = "SEL4_CONFIG_BENCHMARK", not(feature = "host")))]
+use core::cmp;
+use super::*;
+
+pub use stats::{Stats, percentile};
+
+#[cfg(all(feature = "SEL4_CONFIG_BENCHMARK", not(feature = "host")))]
+const ENTRY_WORDS: usize = 2;
+
+/// One record from the kernel benchmark log.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LogEntry {
+    pub event: seL4_Word,
+    pub timestamp: seL4_Word,
+}
+
+/// Reset the log, run `f` and read what the kernel logged meanwhile.
+///
+/// Returns the part of `entries` that was filled in. Entries that do not fit
+/// in `entries` are dropped.
+#[cfg(all(feature = "SEL4_CONFIG_BENCHMARK", not(feature = "host")))]
+pub fn record<F: FnOnce()>(f: F, entries: &mut [LogEntry]) -> &mut [LogEntry] {
+    unsafe { seL4_BenchmarkResetLog() };
+    f();
+    read_log(entries)
+}
+
+/// Read the current contents of the kernel log into `entries`.
+///
+/// Returns the part of `entries` that was filled in.
+#[cfg(all(feature = "SEL4_CONFIG_BENCHMARK", not(feature = "host")))]
+pub fn read_log(entries: &mut [LogEntry]) -> &mut [LogEntry] {
+    let logged = unsafe { seL4_BenchmarkLogSize() } as usize / ENTRY_WORDS;
+    let total = cmp::min(logged, entries.len());
+    let chunk = seL4_MsgMaxLength / ENTRY_WORDS;
+
+    let mut read = 0;
+    while read < total {
+        let want = cmp::min(chunk, total - read);
+        let words = unsafe {
+            seL4_BenchmarkDumpLog((read * ENTRY_WORDS) as seL4_Word,
+                                  (want * ENTRY_WORDS) as seL4_Word)
+        } as usize;
+        let got = cmp::min(words / ENTRY_WORDS, want);
+        if got == 0 {
+            break;
+        }
+
+        for i in 0..got {
+            let mr = (i * ENTRY_WORDS) as isize;
+            entries[read + i] = unsafe {
+                LogEntry {
+                    event: seL4_GetMR(mr),
+                    timestamp: seL4_GetMR(mr + 1),
+                }
+            };
+        }
+        read += got;
+    }
+
+    &mut entries[..read]
+}
+
+/// Cycles between consecutive log entries.
+///
+/// Writes `entries.len() - 1` intervals into `out` (or as many as fit) and
+/// returns how many were written. Counter wraparound is accounted for.
+pub fn intervals(entries: &[LogEntry], out: &mut [u32]) -> usize {
+    let mut n = 0;
+    for (pair, slot) in entries.windows(2).zip(out.iter_mut()) {
+        *slot = pair[1].timestamp.wrapping_sub(pair[0].timestamp);
+        n += 1;
+    }
+    n
+}
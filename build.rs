@@ -53,4 +53,40 @@ fn main() {
         .status().unwrap().success());
     std::mem::forget(bfin);
     std::mem::forget(bfout);
+
+    if env::var("CARGO_FEATURE_RUNTIME").is_ok() {
+        build_start(&out_dir);
+    }
+}
+
+fn build_start(out_dir: &str) {
+    let target = env::var("TARGET").unwrap();
+    let (cc, cflags, ar, src) = if target.starts_with("arm") {
+        (tool("CC", "arm-linux-gnueabi-gcc"), &[][..], tool("AR", "arm-linux-gnueabi-ar"),
+         "arch/arm_start.S")
+    } else {
+        (tool("CC", "gcc"), &["-m32"][..], tool("AR", "ar"), "arch/x86_start.S")
+    };
+
+    let obj = format!("{}/start.o", out_dir);
+    let defines: &[&str] = if env::var("CARGO_FEATURE_TLS").is_ok() { &["-DSEL4_TLS"] } else { &[] };
+    assert!(Command::new(&cc)
+        .args(cflags)
+        .args(defines)
+        .args(&["-c", src, "-o", &*obj])
+        .status().unwrap().success());
+    assert!(Command::new(&ar)
+        .args(&["crus", &*format!("{}/libsel4_start.a", out_dir), &*obj])
+        .status().unwrap().success());
+
+    println!("cargo:rustc-link-search=native={}", out_dir);
+    println!("cargo:rustc-link-lib=static=sel4_start");
+}
+
+/* The C compiler or archiver to use: TARGET_CC (TARGET_AR), then CC (AR),
+ * then the usual cross tool for the arch. */
+fn tool(var: &str, default: &str) -> String {
+    env::var(format!("TARGET_{}", var))
+        .or_else(|_| env::var(var))
+        .unwrap_or_else(|_| default.to_owned())
 }
@@ -0,0 +1,170 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+//! Helpers built on the kernel's debug syscalls.
+//!
+//! Everything that talks to the kernel here needs the `SEL4_DEBUG` feature.
+//! The print macros are always available but expand to nothing without it.
+
+#[cfg(feature = "SEL4_DEBUG")]
+use core::{cmp, fmt};
+#[cfg(feature = "SEL4_DEBUG")]
+use super::*;
+
+/// The kernel debug console, written one byte at a time with
+/// `seL4_DebugPutChar`.
+///
+/// `\n` is sent as `\r\n` so output lines up on a serial terminal.
+#[cfg(feature = "SEL4_DEBUG")]
+#[derive(Clone, Copy)]
+pub struct DebugConsole;
+
+#[cfg(feature = "SEL4_DEBUG")]
+impl DebugConsole {
+    /// Write raw bytes, translating newlines.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            if b == b'\n' {
+                unsafe { seL4_DebugPutChar(b'\r') };
+            }
+            unsafe { seL4_DebugPutChar(b) };
+        }
+    }
+}
+
+#[cfg(feature = "SEL4_DEBUG")]
+impl fmt::Write for DebugConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(feature = "SEL4_DEBUG")]
+impl fmt::Display for seL4_CapType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            seL4_CapType::Unknown(tag) => write!(f, "unknown ({})", tag),
+            _ => f.write_str(self.name()),
+        }
+    }
+}
+
+/// Ask the kernel what kind of capability is in `cap`.
+#[cfg(feature = "SEL4_DEBUG")]
+pub fn identify_cap(cap: seL4_CPtr) -> seL4_CapType {
+    seL4_CapType::from_tag(unsafe { seL4_DebugCapIdentify(cap) })
+}
+
+/// Print every occupied slot of the initial thread's CNode.
+///
+/// Each line gives the slot number, the kind of cap in it and where the
+/// cap came from: one of the fixed initial caps, or the bootinfo region
+/// that covers the slot.
+#[cfg(feature = "SEL4_DEBUG")]
+pub fn dump_cspace(bootinfo: &seL4_BootInfo) {
+    let slots = 1 << bootinfo.initThreadCNodeSizeBits;
+
+    debug_println!("{:>6}  {:<16}  {}", "slot", "cap", "region");
+    for slot in 0..slots {
+        let kind = identify_cap(slot);
+        if kind == seL4_CapType::NullCap {
+            continue;
+        }
+        debug_println!("{:>6}  {:<16}  {}", slot, kind, slot_region(bootinfo, slot));
+    }
+}
+
+#[cfg(feature = "SEL4_DEBUG")]
+fn slot_region(bootinfo: &seL4_BootInfo, slot: seL4_Word) -> &'static str {
+    fn contains(region: &seL4_SlotRegion, slot: seL4_Word) -> bool {
+        region.start <= slot && slot < region.end
+    }
+
+    static INITIAL_CAPS: [&'static str; 12] = [
+        "null", "init TCB", "init CNode", "init VSpace", "IRQ control",
+        "ASID control", "init ASID pool", "IO port", "IO space",
+        "bootinfo frame", "init IPC buffer", "domain",
+    ];
+
+    if (slot as usize) < INITIAL_CAPS.len() {
+        return INITIAL_CAPS[slot as usize];
+    }
+    if contains(&bootinfo.sharedFrames, slot) {
+        return "sharedFrames";
+    }
+    if contains(&bootinfo.userImageFrames, slot) {
+        return "userImageFrames";
+    }
+    if contains(&bootinfo.userImagePTs, slot) {
+        return "userImagePTs";
+    }
+    if contains(&bootinfo.untyped, slot) {
+        return "untyped";
+    }
+    for region in &bootinfo.deviceRegions[..bootinfo.numDeviceRegions as usize] {
+        if contains(&region.frames, slot) {
+            return "deviceRegions";
+        }
+    }
+    if contains(&bootinfo.empty, slot) {
+        return "empty";
+    }
+    "-"
+}
+
+/// Give `tcb` a name in kernel debug output.
+///
+/// Names longer than `seL4_MaxThreadNameLength` bytes are truncated at the
+/// last character that fits.
+#[cfg(feature = "SEL4_DEBUG")]
+pub fn name_thread(tcb: seL4_TCB, name: &str) {
+    let bytes = name.as_bytes();
+    let mut len = cmp::min(bytes.len(), seL4_MaxThreadNameLength);
+    /* Back off over UTF-8 continuation bytes to a character boundary. */
+    while len < bytes.len() && bytes[len] & 0xc0 == 0x80 {
+        len -= 1;
+    }
+    unsafe { seL4_DebugNameThread(tcb, &bytes[..len]) }
+}
+
+/// Name the root task's initial thread. Other threads should use
+/// `name_thread` with their TCB cap.
+#[cfg(feature = "SEL4_DEBUG")]
+pub fn name_root_thread(name: &str) {
+    name_thread(seL4_Cap::seL4_CapInitThreadTCB as seL4_TCB, name)
+}
+
+/// Print to the kernel debug console.
+///
+/// Expands to nothing unless the `SEL4_DEBUG` feature is enabled.
+#[cfg(feature = "SEL4_DEBUG")]
+#[macro_export]
+macro_rules! debug_print {
+    ($($arg:tt)*) => ({
+        use ::core::fmt::Write;
+        let _ = write!($crate::debug::DebugConsole, $($arg)*);
+    })
+}
+
+#[cfg(not(feature = "SEL4_DEBUG"))]
+#[macro_export]
+macro_rules! debug_print {
+    ($($arg:tt)*) => ({ })
+}
+
+/// Print to the kernel debug console, with a newline.
+///
+/// Expands to nothing unless the `SEL4_DEBUG` feature is enabled.
+#[macro_export]
+macro_rules! debug_println {
+    ($fmt:expr) => (debug_print!(concat!($fmt, "\n")));
+    ($fmt:expr, $($arg:tt)*) => (debug_print!(concat!($fmt, "\n"), $($arg)*));
+}
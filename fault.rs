@@ -0,0 +1,484 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+//! Decoding fault messages.
+//!
+//! When a thread faults, the kernel sends a message on its fault endpoint
+//! whose label is the fault type and whose message registers describe the
+//! fault. After a `seL4_Wait` on the fault endpoint returns, `Fault::new`
+//! turns the received message into a `Fault`.
+//!
+//! Replying to the fault message resumes the faulting thread. An empty
+//! reply (`restart`) retries the faulting instruction; unknown syscalls and
+//! user exceptions can instead be answered with new register values.
+
+use super::*;
+
+/// Fault types, as sent in the message label.
+pub const seL4_NoFault: seL4_Word = 0;
+pub const seL4_CapFault: seL4_Word = 1;
+pub const seL4_VMFault: seL4_Word = 2;
+pub const seL4_UnknownSyscall: seL4_Word = 3;
+pub const seL4_UserException: seL4_Word = 4;
+
+/// A decoded fault message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    Cap(CapFault),
+    VM(VMFault),
+    UnknownSyscall(UnknownSyscall),
+    UserException(UserException),
+    /// A label that is not a known fault type, with the message length.
+    Unknown(seL4_Word, seL4_Word),
+}
+
+impl Fault {
+    /// Decode the fault message that `info` describes from the IPC buffer.
+    ///
+    /// Must be called before anything else overwrites the message registers.
+    pub unsafe fn new(info: seL4_MessageInfo) -> Fault {
+        match info.get_label() {
+            seL4_CapFault => Fault::Cap(CapFault::new()),
+            seL4_VMFault => Fault::VM(VMFault::new()),
+            seL4_UnknownSyscall => Fault::UnknownSyscall(UnknownSyscall::new()),
+            seL4_UserException => Fault::UserException(UserException::new()),
+            label => Fault::Unknown(label, info.get_length()),
+        }
+    }
+
+    /// Address of the faulting instruction.
+    pub fn ip(&self) -> Option<seL4_Word> {
+        match *self {
+            Fault::Cap(ref f) => Some(f.ip),
+            Fault::VM(ref f) => Some(f.ip),
+            Fault::UnknownSyscall(ref f) => Some(f.ip()),
+            Fault::UserException(ref f) => Some(f.ip),
+            Fault::Unknown(..) => None,
+        }
+    }
+}
+
+/// Reply message that resumes the faulting thread where it stopped.
+///
+/// For VM and cap faults this retries the faulting instruction.
+pub fn restart() -> seL4_MessageInfo {
+    seL4_MessageInfo::new(0, 0, 0, 0)
+}
+
+/// Why a capability lookup failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LookupFailure {
+    NoFailure,
+    InvalidRoot,
+    MissingCapability { bits_left: seL4_Word },
+    DepthMismatch { bits_left: seL4_Word, bits_found: seL4_Word },
+    GuardMismatch { bits_left: seL4_Word, guard_found: seL4_Word, bits_found: seL4_Word },
+    Unknown(seL4_Word),
+}
+
+/// A fault while looking up a capability.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CapFault {
+    pub ip: seL4_Word,
+    /// Capability address that failed to resolve.
+    pub addr: seL4_Word,
+    /// Whether the fault happened while receiving.
+    pub in_receive_phase: bool,
+    pub lookup_failure: LookupFailure,
+}
+
+impl CapFault {
+    unsafe fn new() -> CapFault {
+        let lookup_failure = match seL4_GetMR(3) {
+            0 => LookupFailure::NoFailure,
+            1 => LookupFailure::InvalidRoot,
+            2 => LookupFailure::MissingCapability { bits_left: seL4_GetMR(4) },
+            3 => LookupFailure::DepthMismatch {
+                bits_left: seL4_GetMR(4),
+                bits_found: seL4_GetMR(5),
+            },
+            4 => LookupFailure::GuardMismatch {
+                bits_left: seL4_GetMR(4),
+                guard_found: seL4_GetMR(5),
+                bits_found: seL4_GetMR(6),
+            },
+            other => LookupFailure::Unknown(other),
+        };
+
+        CapFault {
+            ip: seL4_GetMR(0),
+            addr: seL4_GetMR(1),
+            in_receive_phase: seL4_GetMR(2) != 0,
+            lookup_failure: lookup_failure,
+        }
+    }
+}
+
+/// A virtual memory fault.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VMFault {
+    pub ip: seL4_Word,
+    /// Faulting address.
+    pub addr: seL4_Word,
+    /// Whether this was an instruction fetch rather than a data access.
+    pub instruction_fault: bool,
+    /// Architecture fault status: the page fault error code on x86, the
+    /// FSR (IFSR for instruction faults) on ARM.
+    pub fsr: seL4_Word,
+}
+
+impl VMFault {
+    unsafe fn new() -> VMFault {
+        VMFault {
+            ip: seL4_GetMR(0),
+            addr: seL4_GetMR(1),
+            instruction_fault: seL4_GetMR(2) != 0,
+            fsr: seL4_GetMR(3),
+        }
+    }
+}
+
+/// A syscall with a number the kernel does not know.
+///
+/// The registers are those of the faulting thread. Replying with
+/// `resume` writes them back, so a handler can emulate the syscall.
+#[cfg(any(target_arch = "x86", feature = "mock"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnknownSyscall {
+    pub eax: seL4_Word,
+    pub ebx: seL4_Word,
+    pub ecx: seL4_Word,
+    pub edx: seL4_Word,
+    pub esi: seL4_Word,
+    pub edi: seL4_Word,
+    pub ebp: seL4_Word,
+    pub eip: seL4_Word,
+    pub esp: seL4_Word,
+    pub eflags: seL4_Word,
+    pub syscall: seL4_Word,
+}
+
+#[cfg(any(target_arch = "x86", feature = "mock"))]
+const UNKNOWN_SYSCALL_REPLY_LENGTH: usize = 10;
+
+#[cfg(any(target_arch = "x86", feature = "mock"))]
+impl UnknownSyscall {
+    unsafe fn new() -> UnknownSyscall {
+        UnknownSyscall {
+            eax: seL4_GetMR(0),
+            ebx: seL4_GetMR(1),
+            ecx: seL4_GetMR(2),
+            edx: seL4_GetMR(3),
+            esi: seL4_GetMR(4),
+            edi: seL4_GetMR(5),
+            ebp: seL4_GetMR(6),
+            eip: seL4_GetMR(7),
+            esp: seL4_GetMR(8),
+            eflags: seL4_GetMR(9),
+            syscall: seL4_GetMR(10),
+        }
+    }
+
+    fn registers(&self) -> [seL4_Word; UNKNOWN_SYSCALL_REPLY_LENGTH] {
+        [self.eax, self.ebx, self.ecx, self.edx, self.esi, self.edi, self.ebp,
+         self.eip, self.esp, self.eflags]
+    }
+
+    pub fn ip(&self) -> seL4_Word {
+        self.eip
+    }
+
+    /// Point the thread past the syscall instruction, so resuming does not
+    /// execute it again.
+    pub fn skip_instruction(&mut self) {
+        /* sysenter and int $0x80 are both two bytes */
+        self.eip += 2;
+    }
+}
+
+/// A syscall with a number the kernel does not know.
+///
+/// The registers are those of the faulting thread. Replying with
+/// `resume` writes them back, so a handler can emulate the syscall.
+#[cfg(all(target_arch = "arm", not(feature = "mock")))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnknownSyscall {
+    pub r0: seL4_Word,
+    pub r1: seL4_Word,
+    pub r2: seL4_Word,
+    pub r3: seL4_Word,
+    pub r4: seL4_Word,
+    pub r5: seL4_Word,
+    pub r6: seL4_Word,
+    pub r7: seL4_Word,
+    pub pc: seL4_Word,
+    pub sp: seL4_Word,
+    pub lr: seL4_Word,
+    pub cpsr: seL4_Word,
+    pub syscall: seL4_Word,
+}
+
+#[cfg(all(target_arch = "arm", not(feature = "mock")))]
+const UNKNOWN_SYSCALL_REPLY_LENGTH: usize = 12;
+
+#[cfg(all(target_arch = "arm", not(feature = "mock")))]
+impl UnknownSyscall {
+    unsafe fn new() -> UnknownSyscall {
+        UnknownSyscall {
+            r0: seL4_GetMR(0),
+            r1: seL4_GetMR(1),
+            r2: seL4_GetMR(2),
+            r3: seL4_GetMR(3),
+            r4: seL4_GetMR(4),
+            r5: seL4_GetMR(5),
+            r6: seL4_GetMR(6),
+            r7: seL4_GetMR(7),
+            pc: seL4_GetMR(8),
+            sp: seL4_GetMR(9),
+            lr: seL4_GetMR(10),
+            cpsr: seL4_GetMR(11),
+            syscall: seL4_GetMR(12),
+        }
+    }
+
+    fn registers(&self) -> [seL4_Word; UNKNOWN_SYSCALL_REPLY_LENGTH] {
+        [self.r0, self.r1, self.r2, self.r3, self.r4, self.r5, self.r6, self.r7,
+         self.pc, self.sp, self.lr, self.cpsr]
+    }
+
+    pub fn ip(&self) -> seL4_Word {
+        self.pc
+    }
+
+    /// Point the thread past the syscall instruction, so resuming does not
+    /// execute it again.
+    pub fn skip_instruction(&mut self) {
+        self.pc += 4;
+    }
+}
+
+impl UnknownSyscall {
+    /// Reply message that resumes the thread with these register values.
+    ///
+    /// Writes the registers into the message registers; pass the result to
+    /// `seL4_Reply` or `seL4_ReplyWait` straight away.
+    pub unsafe fn resume(&self) -> seL4_MessageInfo {
+        let regs = self.registers();
+        for (i, &r) in regs.iter().enumerate() {
+            seL4_SetMR(i as isize, r);
+        }
+        seL4_MessageInfo::new(0, 0, 0, regs.len() as seL4_Word)
+    }
+}
+
+/// An architecture exception, such as a divide error or an undefined
+/// instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UserException {
+    pub ip: seL4_Word,
+    pub sp: seL4_Word,
+    /// EFLAGS on x86, CPSR on ARM.
+    pub flags: seL4_Word,
+    /// Exception number.
+    pub number: seL4_Word,
+    /// Exception error code.
+    pub code: seL4_Word,
+}
+
+impl UserException {
+    unsafe fn new() -> UserException {
+        UserException {
+            ip: seL4_GetMR(0),
+            sp: seL4_GetMR(1),
+            flags: seL4_GetMR(2),
+            number: seL4_GetMR(3),
+            code: seL4_GetMR(4),
+        }
+    }
+
+    /// Reply message that resumes the thread with `ip`, `sp` and `flags`.
+    ///
+    /// Writes the registers into the message registers; pass the result to
+    /// `seL4_Reply` or `seL4_ReplyWait` straight away.
+    pub unsafe fn resume(&self) -> seL4_MessageInfo {
+        seL4_SetMR(0, self.ip);
+        seL4_SetMR(1, self.sp);
+        seL4_SetMR(2, self.flags);
+        seL4_MessageInfo::new(0, 0, 0, 3)
+    }
+}
+
+/// Maximum number of threads a `FaultService` can watch.
+pub const MAX_FAULT_THREADS: usize = 32;
+
+/// What to do with a faulting thread.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Reply, resuming the thread. For unknown syscalls and user exceptions
+    /// the (possibly modified) registers of the fault are written back; other
+    /// faults retry the faulting instruction.
+    Resume,
+    /// Reply with `restart`, resuming the thread with its registers as they
+    /// were. An unknown syscall or user exception is executed again; to step
+    /// over it instead, move its `ip` and `Resume`.
+    Restart,
+    /// Suspend the thread. It can be restarted later through its TCB. For a
+    /// message from an unregistered badge there is no TCB to suspend, so
+    /// the sender is left blocked as with `Ignore`.
+    Kill,
+    /// Leave the thread blocked without a reply.
+    Ignore,
+}
+
+/// A thread whose faults are being handled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Thread {
+    /// Badge of the fault endpoint cap the thread was configured with.
+    pub badge: seL4_Word,
+    pub tcb: seL4_TCB,
+}
+
+/// Callbacks for a `FaultService`. Every fault kills the thread unless the
+/// handler overrides the corresponding method.
+pub trait FaultHandler {
+    fn cap_fault(&mut self, _thread: Thread, _fault: &CapFault) -> Action {
+        Action::Kill
+    }
+
+    fn vm_fault(&mut self, _thread: Thread, _fault: &VMFault) -> Action {
+        Action::Kill
+    }
+
+    fn unknown_syscall(&mut self, _thread: Thread, _fault: &mut UnknownSyscall) -> Action {
+        Action::Kill
+    }
+
+    fn user_exception(&mut self, _thread: Thread, _fault: &mut UserException) -> Action {
+        Action::Kill
+    }
+
+    /// A message from a badge that was never registered, or with an
+    /// unknown label. By default it is left without a reply; `Resume` and
+    /// `Restart` both reply with `restart`.
+    fn unexpected(&mut self, _badge: seL4_Word, _info: seL4_MessageInfo) -> Action {
+        Action::Ignore
+    }
+}
+
+/// A fault handling loop on one endpoint.
+///
+/// Each watched thread must have a cap to `endpoint` minted with a distinct
+/// badge as its fault endpoint; `register` tells the service which TCB that
+/// badge belongs to.
+pub struct FaultService<H> {
+    endpoint: seL4_CPtr,
+    threads: [Option<Thread>; MAX_FAULT_THREADS],
+    handler: H,
+}
+
+impl<H: FaultHandler> FaultService<H> {
+    pub fn new(endpoint: seL4_CPtr, handler: H) -> FaultService<H> {
+        FaultService {
+            endpoint: endpoint,
+            threads: [None; MAX_FAULT_THREADS],
+            handler: handler,
+        }
+    }
+
+    /// Handle faults sent with `badge` as coming from `tcb`.
+    ///
+    /// Fails if the table is full or the badge is already registered.
+    pub fn register(&mut self, badge: seL4_Word, tcb: seL4_TCB) -> Result<(), ()> {
+        if self.lookup(badge).is_some() {
+            return Err(());
+        }
+        match self.threads.iter_mut().find(|t| t.is_none()) {
+            Some(slot) => {
+                *slot = Some(Thread { badge: badge, tcb: tcb });
+                Ok(())
+            }
+            None => Err(()),
+        }
+    }
+
+    /// Stop handling faults for `badge`.
+    pub fn unregister(&mut self, badge: seL4_Word) {
+        for slot in self.threads.iter_mut() {
+            if slot.map_or(false, |t| t.badge == badge) {
+                *slot = None;
+            }
+        }
+    }
+
+    pub fn handler(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    fn lookup(&self, badge: seL4_Word) -> Option<Thread> {
+        self.threads.iter().filter_map(|t| *t).find(|t| t.badge == badge)
+    }
+
+    /// Handle one received fault message.
+    ///
+    /// Returns the reply to send, if any. A thread that cannot be suspended
+    /// for `Kill` is reported on the debug console and left without a reply.
+    pub unsafe fn dispatch(&mut self, badge: seL4_Word, info: seL4_MessageInfo)
+                           -> Option<seL4_MessageInfo> {
+        let (action, reply, tcb) = match self.lookup(badge) {
+            Some(thread) => {
+                let (action, reply) = match Fault::new(info) {
+                    Fault::Cap(f) => (self.handler.cap_fault(thread, &f), restart()),
+                    Fault::VM(f) => (self.handler.vm_fault(thread, &f), restart()),
+                    Fault::UnknownSyscall(mut f) => {
+                        let action = self.handler.unknown_syscall(thread, &mut f);
+                        (action, f.resume())
+                    }
+                    Fault::UserException(mut f) => {
+                        let action = self.handler.user_exception(thread, &mut f);
+                        (action, f.resume())
+                    }
+                    Fault::Unknown(..) => (self.handler.unexpected(badge, info), restart()),
+                };
+                (action, reply, Some(thread.tcb))
+            }
+            None => (self.handler.unexpected(badge, info), restart(), None),
+        };
+
+        match action {
+            Action::Resume => Some(reply),
+            Action::Restart => Some(restart()),
+            Action::Kill => {
+                if let Some(tcb) = tcb {
+                    let err = seL4_TCB_Suspend(tcb);
+                    if err != 0 {
+                        debug_println!("fault: cannot suspend the thread with badge {}: error {}",
+                                       badge, err);
+                    }
+                }
+                None
+            }
+            Action::Ignore => None,
+        }
+    }
+
+    /// Wait for faults and handle them, forever.
+    pub fn run(&mut self) -> ! {
+        let mut badge: seL4_Word = 0;
+        unsafe {
+            let mut info = seL4_Wait(self.endpoint, &mut badge);
+            loop {
+                info = match self.dispatch(badge, info) {
+                    Some(reply) => seL4_ReplyWait(self.endpoint, reply, &mut badge),
+                    None => seL4_Wait(self.endpoint, &mut badge),
+                };
+            }
+        }
+    }
+}
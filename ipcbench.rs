@@ -0,0 +1,272 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+//! IPC microbenchmarks for the root task.
+//!
+//! `run` retypes an endpoint, an async endpoint and a server TCB out of the
+//! root task's untyped memory, then times each IPC path with the cycle
+//! counter. Results go to the debug console, one line per benchmark:
+//!
+//! ```text
+//! ipcbench name=call mrs=0 caps=0 count=100 min=.. max=.. mean=.. median=.. p90=.. p99=..
+//! ```
+//!
+//! Messages of up to two words without caps can take the kernel fastpath;
+//! longer messages and cap transfers go through the slowpath, so both kinds
+//! are measured.
+//!
+//! The server thread runs on a static stack and uses a page of the root
+//! task's own image as its IPC buffer, so `run` can only be used once.
+//!
+//! The root task has to be at the highest priority, where the kernel starts
+//! it. `run` lowers it for the one-way benchmarks and puts it back there at
+//! the end; the kernel has no call to read a priority back.
+
+use core::cmp;
+use core::intrinsics::{volatile_load, volatile_store};
+use core::ptr;
+use super::*;
+use stats::Stats;
+use thread::{self, IpcBuffer, IPC_BUFFER_PAGES};
+use time::cycles;
+
+/// Number of timed iterations per benchmark.
+pub const ITERATIONS: usize = 100;
+
+/* Untimed iterations run before each benchmark to warm the caches. */
+const WARMUP: usize = 10;
+
+const PAGE_SIZE: usize = 1 << seL4_PageBits;
+const STACK_SIZE: usize = 4 * PAGE_SIZE;
+const MAX_PRIO: u8 = 255;
+
+/* Message lengths measured; the first three fit in the fastpath. */
+const LENGTHS: [usize; 5] = [0, 1, 2, 3, 8];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    ReplyWait,
+    Wait,
+    WaitNotification,
+    RestorePriority,
+}
+
+static mut SERVER_MODE: Mode = Mode::ReplyWait;
+static mut SERVER_IPC_BUFFER: seL4_Word = 0;
+static mut ENDPOINT: seL4_CPtr = 0;
+static mut NOTIFICATION: seL4_CPtr = 0;
+static mut REPLY_LENGTH: seL4_Word = 0;
+
+/* Shared between the timing thread and the server for one-way benchmarks. */
+static mut SEND_TIME: u64 = 0;
+static mut RECEIVED: usize = 0;
+static mut SAMPLES: [u32; ITERATIONS + WARMUP] = [0; ITERATIONS + WARMUP];
+
+static mut SERVER_STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+static mut SERVER_IPC_PAGES: [u8; IPC_BUFFER_PAGES] = [0; IPC_BUFFER_PAGES];
+
+struct Server {
+    tcb: seL4_TCB,
+}
+
+/// Run every benchmark and print the results.
+pub fn run(bootinfo: &seL4_BootInfo) {
+    let server = match unsafe { setup(bootinfo) } {
+        Ok(server) => server,
+        Err(what) => {
+            debug_println!("ipcbench error={}", what);
+            return;
+        }
+    };
+
+    /* Round trips run with client and server at the same priority. */
+    unsafe { server.start(Mode::ReplyWait) };
+    for &len in LENGTHS.iter() {
+        bench_call(len, 0);
+    }
+    bench_call(0, 1);
+    bench_yield();
+
+    /* One-way benchmarks need the server to preempt us as soon as it is
+     * woken, so drop below it. */
+    unsafe {
+        seL4_TCB_SetPriority(seL4_Cap::seL4_CapInitThreadTCB as seL4_TCB, MAX_PRIO - 1);
+    }
+
+    unsafe { server.start(Mode::Wait) };
+    for &len in LENGTHS.iter() {
+        bench_send(len, 0);
+    }
+    bench_send(0, 1);
+
+    unsafe { server.start(Mode::WaitNotification) };
+    bench_notify();
+
+    /* We cannot raise ourselves above our own priority; the server can. */
+    unsafe {
+        server.start(Mode::RestorePriority);
+        seL4_TCB_Suspend(server.tcb);
+    }
+}
+
+unsafe fn setup(bootinfo: &seL4_BootInfo) -> Result<Server, &'static str> {
+    let endpoint = bootinfo.empty.start;
+    let notification = endpoint + 1;
+    let tcb = endpoint + 2;
+    try!(thread::retype_from_bootinfo(bootinfo, seL4_PageBits,
+                                      &[seL4_ObjectType::seL4_EndpointObject,
+                                        seL4_ObjectType::seL4_AsyncEndpointObject,
+                                        seL4_ObjectType::seL4_TCBObject],
+                                      endpoint));
+    let buffer = try!(IpcBuffer::in_image(bootinfo, &mut SERVER_IPC_PAGES));
+
+    let err = seL4_TCB_Configure(tcb, seL4_Cap::seL4_CapNull as seL4_Word, MAX_PRIO,
+                                 seL4_Cap::seL4_CapInitThreadCNode as seL4_CNode,
+                                 seL4_CapData { words: [0] },
+                                 seL4_Cap::seL4_CapInitThreadVSpace as seL4_CPtr,
+                                 seL4_CapData { words: [0] },
+                                 buffer.vaddr, buffer.frame);
+    if err != 0 {
+        return Err("configure");
+    }
+
+    ENDPOINT = endpoint;
+    NOTIFICATION = notification;
+    SERVER_IPC_BUFFER = buffer.vaddr;
+
+    Ok(Server { tcb: tcb })
+}
+
+impl Server {
+    /// (Re)start the server thread at its entry point in `mode`.
+    unsafe fn start(&self, mode: Mode) {
+        seL4_TCB_Suspend(self.tcb);
+        SERVER_MODE = mode;
+        RECEIVED = 0;
+
+        let regs = seL4_UserContext::new_thread(server_entry as seL4_Word,
+                                                (SERVER_STACK.as_ptr() as usize + STACK_SIZE) as seL4_Word);
+        let _ = thread::write_all_registers(self.tcb, true, &regs);
+    }
+}
+
+extern fn server_entry() -> ! {
+    unsafe {
+        seL4_SetUserData(SERVER_IPC_BUFFER);
+        match SERVER_MODE {
+            Mode::ReplyWait => {
+                seL4_Wait(ENDPOINT, ptr::null_mut());
+                loop {
+                    let reply = seL4_MessageInfo::new(0, 0, 0, volatile_load(&REPLY_LENGTH));
+                    seL4_ReplyWait(ENDPOINT, reply, ptr::null_mut());
+                }
+            }
+            Mode::Wait => loop {
+                seL4_Wait(ENDPOINT, ptr::null_mut());
+                record_receive();
+            },
+            Mode::WaitNotification => loop {
+                seL4_Wait(NOTIFICATION, ptr::null_mut());
+                record_receive();
+            },
+            Mode::RestorePriority => {
+                seL4_TCB_SetPriority(seL4_Cap::seL4_CapInitThreadTCB as seL4_TCB, MAX_PRIO);
+                loop {
+                    seL4_Wait(ENDPOINT, ptr::null_mut());
+                }
+            }
+        }
+    }
+}
+
+unsafe fn record_receive() {
+    let now = cycles();
+    let i = volatile_load(&RECEIVED);
+    if i < SAMPLES.len() {
+        volatile_store(&mut SAMPLES[i], (now - volatile_load(&SEND_TIME)) as u32);
+    }
+    volatile_store(&mut RECEIVED, i + 1);
+}
+
+/* The timed samples the server recorded, skipping the warmup. */
+unsafe fn received() -> &'static mut [u32] {
+    let n = cmp::min(volatile_load(&RECEIVED), SAMPLES.len());
+    &mut SAMPLES[cmp::min(WARMUP, n)..n]
+}
+
+fn message(len: usize, caps: usize) -> seL4_MessageInfo {
+    unsafe {
+        /* Sending the endpoint to itself gets the cap unwrapped into a
+         * badge, so the server needs no receive slot. */
+        for i in 0..caps {
+            seL4_SetCap(i as isize, ENDPOINT);
+        }
+    }
+    seL4_MessageInfo::new(0, 0, caps as seL4_Word, len as seL4_Word)
+}
+
+fn bench_call(len: usize, caps: usize) {
+    unsafe {
+        volatile_store(&mut REPLY_LENGTH, len as seL4_Word);
+        for i in 0..(WARMUP + ITERATIONS) {
+            let info = message(len, caps);
+            let start = cycles();
+            seL4_Call(ENDPOINT, info);
+            let end = cycles();
+            SAMPLES[i] = (end - start) as u32;
+        }
+        report("call", len, caps, &mut SAMPLES[WARMUP..]);
+    }
+}
+
+fn bench_send(len: usize, caps: usize) {
+    unsafe {
+        volatile_store(&mut RECEIVED, 0);
+        for _ in 0..(WARMUP + ITERATIONS) {
+            let info = message(len, caps);
+            volatile_store(&mut SEND_TIME, cycles());
+            seL4_Send(ENDPOINT, info);
+        }
+        report("send", len, caps, received());
+    }
+}
+
+fn bench_notify() {
+    unsafe {
+        volatile_store(&mut RECEIVED, 0);
+        for _ in 0..(WARMUP + ITERATIONS) {
+            volatile_store(&mut SEND_TIME, cycles());
+            seL4_Notify(NOTIFICATION, 1);
+        }
+        report("notify", 0, 0, received());
+    }
+}
+
+fn bench_yield() {
+    unsafe {
+        for i in 0..(WARMUP + ITERATIONS) {
+            let start = cycles();
+            seL4_Yield();
+            let end = cycles();
+            SAMPLES[i] = (end - start) as u32;
+        }
+        report("yield", 0, 0, &mut SAMPLES[WARMUP..]);
+    }
+}
+
+fn report(name: &str, len: usize, caps: usize, samples: &mut [u32]) {
+    match Stats::new(samples) {
+        Some(s) => debug_println!("ipcbench name={} mrs={} caps={} count={} min={} max={} \
+                                   mean={} median={} p90={} p99={}",
+                                  name, len, caps, s.count, s.min, s.max,
+                                  s.mean, s.median, s.p90, s.p99),
+        None => debug_println!("ipcbench name={} mrs={} caps={} count=0", name, len, caps),
+    }
+}
@@ -8,32 +8,103 @@
  * according to those terms.
  */
 #![no_std]
-#![feature(asm, no_std)]
-#![feature(core_slice_ext)]
+#![cfg_attr(not(feature = "host"), feature(asm, no_std))]
+#![cfg_attr(not(feature = "host"), feature(core_slice_ext))]
+#![cfg_attr(not(feature = "host"), feature(core_intrinsics))]
+#![cfg_attr(not(feature = "host"), feature(deprecated))]
+#![cfg_attr(feature = "panic_handler", feature(lang_items))]
 #![allow(bad_style, unused_parens, unused_assignments)]
 #![doc(html_root_url = "https://doc.robigalia.org/")]
 
-#[cfg(all(target_arch = "x86", target_pointer_width = "32"))]
+// The `host` feature builds only the data definitions - the ia32 types and
+// constants, the bitfield types, `InvocationLabel`, `SyscallId` and the
+// bootinfo layout - for tools running on the host. It needs no unstable
+// features and has no syscall layer, so it also overrides `mock`; the
+// tests that need the mock kernel are skipped when both are enabled.
+//
+// The `tls` feature changes the IPC buffer ABI on x86; see `tls`.
+
+#[cfg(feature = "log")]
+extern crate log;
+
+#[cfg(all(feature = "mock", not(feature = "host")))]
+#[macro_use]
+extern crate std;
+
+// These come before the arch files: the syscall functions use the trace
+// hooks, and the trace dump uses the debug macros.
+#[cfg(not(feature = "host"))]
+#[macro_use]
+pub mod debug;
+
+#[cfg(not(feature = "host"))]
+#[macro_use]
+pub mod trace;
+
+#[cfg(any(all(target_arch = "x86", target_pointer_width = "32"), feature = "mock", feature = "host"))]
+include!("arch/x86_types.rs");
+
+#[cfg(all(target_arch = "x86", target_pointer_width = "32", not(any(feature = "mock", feature = "host"))))]
 include!("arch/x86.rs");
 
-#[cfg(all(target_arch = "arm", target_pointer_width = "32"))]
+#[cfg(all(feature = "mock", not(feature = "host")))]
+include!("arch/mock.rs");
+
+#[cfg(all(target_arch = "arm", target_pointer_width = "32", not(any(feature = "mock", feature = "host"))))]
 include!("arch/arm.rs");
 
-#[cfg(all(target_arch = "x86", target_pointer_width = "32"))]
+#[cfg(any(all(target_arch = "x86", target_pointer_width = "32"), feature = "mock", feature = "host"))]
 include!(concat!(env!("OUT_DIR"), "/x86_invocation.rs"));
 
-#[cfg(all(target_arch = "arm", target_pointer_width = "32"))]
+#[cfg(all(target_arch = "arm", target_pointer_width = "32", not(any(feature = "mock", feature = "host"))))]
 include!(concat!(env!("OUT_DIR"), "/arm_invocation.rs"));
 
-#[cfg(all(target_arch = "x86", target_pointer_width = "32"))]
+#[cfg(all(any(all(target_arch = "x86", target_pointer_width = "32"), feature = "mock"), not(feature = "host")))]
 include!(concat!(env!("OUT_DIR"), "/x86_syscall_stub.rs"));
 
-#[cfg(all(target_arch = "arm", target_pointer_width = "32"))]
+#[cfg(all(target_arch = "arm", target_pointer_width = "32", not(any(feature = "mock", feature = "host"))))]
 include!(concat!(env!("OUT_DIR"), "/arm_syscall_stub.rs"));
 
 include!(concat!(env!("OUT_DIR"), "/types.rs"));
 include!(concat!(env!("OUT_DIR"), "/syscalls.rs"));
 
 //include!(concat!(env!("OUT_DIR"), "/bootinfo.rs"));
-#[cfg(all(target_arch = "x86", target_pointer_width = "32"))]
 include!("arch/bootinfo.rs");
+
+#[cfg(all(feature = "runtime", not(feature = "host")))]
+pub mod runtime;
+
+#[cfg(all(feature = "panic_handler", not(any(feature = "mock", feature = "host"))))]
+pub mod panic;
+
+#[cfg(all(feature = "log", not(feature = "host")))]
+pub mod logger;
+
+#[cfg(any(feature = "SEL4_CONFIG_BENCHMARK", feature = "host"))]
+pub mod benchmark;
+
+pub mod stats;
+
+#[cfg(not(feature = "host"))]
+pub mod time;
+
+#[cfg(not(feature = "host"))]
+pub mod fault;
+
+#[cfg(not(feature = "host"))]
+pub mod thread;
+
+#[cfg(all(feature = "tls", not(any(feature = "mock", feature = "host"))))]
+pub mod tls;
+
+#[cfg(all(feature = "mock", not(feature = "host")))]
+pub mod mock;
+
+#[cfg(all(feature = "mock", not(feature = "host")))]
+pub mod replay;
+
+#[cfg(all(feature = "SEL4_DEBUG", target_arch = "x86", not(any(feature = "mock", feature = "host"))))]
+pub mod ipcbench;
+
+#[cfg(all(feature = "runtime", feature = "SEL4_DEBUG", not(any(feature = "mock", feature = "host"))))]
+pub mod testing;
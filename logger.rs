@@ -0,0 +1,66 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+//! A `log` backend that writes to the kernel debug console.
+//!
+//! Records come out as `[LEVEL name] message`, where `name` is the optional
+//! thread name given to the logger. Without `SEL4_DEBUG` the logger accepts
+//! nothing and prints nothing.
+//!
+//! The `log_max_level_*` features set the `log` crate's compile-time level
+//! filter, so disabled log statements are compiled out entirely.
+
+use log::{self, Log, LogLevelFilter, LogMetadata, LogRecord, SetLoggerError};
+
+/// Logger routed through `seL4_DebugPutChar`.
+pub struct DebugLogger {
+    /// Name printed with every record, usually the name of the logging thread.
+    pub name: Option<&'static str>,
+    /// Most verbose level that gets printed.
+    pub level: LogLevelFilter,
+}
+
+impl Log for DebugLogger {
+    #[cfg(feature = "SEL4_DEBUG")]
+    fn enabled(&self, metadata: &LogMetadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    #[cfg(not(feature = "SEL4_DEBUG"))]
+    fn enabled(&self, _metadata: &LogMetadata) -> bool {
+        false
+    }
+
+    #[cfg(feature = "SEL4_DEBUG")]
+    fn log(&self, record: &LogRecord) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match self.name {
+            Some(name) => debug_println!("[{} {}] {}", record.level(), name, record.args()),
+            None => debug_println!("[{}] {}", record.level(), record.args()),
+        }
+    }
+
+    #[cfg(not(feature = "SEL4_DEBUG"))]
+    fn log(&self, _record: &LogRecord) { }
+}
+
+/// Install `logger` as the global logger.
+///
+/// Fails if a logger has already been installed.
+pub fn init(logger: &'static DebugLogger) -> Result<(), SetLoggerError> {
+    unsafe {
+        log::set_logger_raw(|max_level| {
+            max_level.set(logger.level);
+            logger as *const Log
+        })
+    }
+}
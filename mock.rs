@@ -0,0 +1,1064 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+//! An in-process model of the seL4 kernel, for testing on the host.
+//!
+//! With the `mock` feature the syscall functions trap into this module
+//! instead of the kernel, so the generated invocation stubs and everything
+//! built on them run as an ordinary program under `cargo test`. The model
+//! follows the ia32 API: words are 32 bits and the invocation labels and
+//! object types are those of x86.
+//!
+//! `boot` creates a kernel and makes the calling host thread its initial
+//! thread, with a CSpace laid out like a real root task's. Further threads
+//! are TCBs retyped from its untyped memory and given a host thread with
+//! `spawn`. Every host thread has its own IPC buffer.
+//!
+//! What is modelled:
+//!
+//! - endpoints: Send, NBSend, Call, Reply, Wait and ReplyWait, with badges
+//!   and cap transfer, including unwrapping caps to the receiving endpoint;
+//! - async endpoints: Notify and Wait, OR-ing badges and message words
+//!   together until a Wait collects them;
+//! - CNodes: Copy, Mint, Move, Delete and Revoke;
+//! - untypeds: Retype into every ia32 object type, with size accounting;
+//! - TCBs: Configure, register reads and writes, SetPriority, SetIPCBuffer,
+//!   Suspend and Resume;
+//! - frames and page tables: Page_Map fails with `seL4_FailedLookup` until a
+//!   page table covers the address.
+//!
+//! Everything else is simplified. CSpaces have one level, so a cptr is a
+//! slot index in the root CNode and depths are ignored. Scheduling is left
+//! to the host, so priorities are recorded but have no effect. No faults are
+//! raised: an invocation of an empty slot fails with
+//! `seL4_InvalidCapability` where the kernel would send a cap fault, and a
+//! `seL4_Send` to one panics. A
+//! suspended thread stops at its next syscall rather than immediately.
+//!
+//! For checking what reaches the kernel rather than what it does, a thread
+//! can `record` the messages it sends and collect them with `recorded`, and
+//! can have its next Call answered with `canned_reply` instead of by the
+//! model. To answer every syscall from a trace captured on real hardware,
+//! see `replay`.
+
+use core::cmp;
+use core::iter;
+use core::mem;
+use std::cell::{RefCell, UnsafeCell};
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::string::String;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::vec::Vec;
+use super::*;
+
+/// Size of the initial thread's CNode, as a power of two.
+pub const ROOT_CNODE_BITS: usize = 12;
+
+/// Sizes of the untypeds given to the initial thread, as powers of two.
+pub const UNTYPED_BITS: [u8; 4] = [24, 20, 16, 12];
+
+/* Badges are 28 bits wide on 32-bit kernels. */
+const BADGE_MASK: seL4_Word = 0x0fff_ffff;
+
+type ObjId = usize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Cap {
+    obj: ObjId,
+    badge: seL4_Word,
+    rights: seL4_Word,
+}
+
+impl Cap {
+    fn new(obj: ObjId) -> Cap {
+        Cap { obj: obj, badge: 0, rights: seL4_CapRights::AllRights as seL4_Word }
+    }
+}
+
+/* A message in flight. Caps that failed to look up are None, which stops
+ * the transfer there, as in the kernel. */
+struct Message {
+    label: seL4_Word,
+    mrs: Vec<seL4_Word>,
+    caps: Vec<Option<Cap>>,
+    badge: seL4_Word,
+}
+
+struct Pending {
+    sender: ObjId,
+    msg: Message,
+    call: bool,
+}
+
+struct Tcb {
+    regs: seL4_UserContext,
+    priority: u8,
+    fault_ep: seL4_CPtr,
+    cspace: Option<Cap>,
+    ipc_buffer: seL4_Word,
+    name: String,
+    suspended: bool,
+    /* Queued on an endpoint, waiting for a receiver. */
+    sending: bool,
+    /* A reply to this thread's Call, not yet picked up. */
+    reply: Option<Message>,
+    /* The thread whose Call this thread has to reply to. */
+    caller: Option<ObjId>,
+}
+
+impl Tcb {
+    fn new() -> Tcb {
+        Tcb {
+            regs: seL4_UserContext::default(),
+            priority: 0,
+            fault_ep: 0,
+            cspace: None,
+            ipc_buffer: 0,
+            name: String::new(),
+            /* New TCBs are inactive until resumed. */
+            suspended: true,
+            sending: false,
+            reply: None,
+            caller: None,
+        }
+    }
+}
+
+enum Object {
+    Untyped { size_bits: usize, used: usize },
+    Endpoint { queue: VecDeque<Pending>, receivers: usize },
+    Notification { badge: seL4_Word, data: seL4_Word, pending: bool },
+    CNode { slots: Vec<Option<Cap>> },
+    Tcb(Tcb),
+    Frame { mapped: bool },
+    PageTable { mapped: bool },
+    PageDirectory { tables: Vec<seL4_Word> },
+    /* Caps the model hands out but cannot invoke, by debug cap tag. */
+    Other(u32),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Untyped,
+    Endpoint,
+    Notification,
+    CNode,
+    Tcb,
+    Frame,
+    PageTable,
+    PageDirectory,
+    Other(u32),
+}
+
+impl Object {
+    fn kind(&self) -> Kind {
+        match *self {
+            Object::Untyped { .. } => Kind::Untyped,
+            Object::Endpoint { .. } => Kind::Endpoint,
+            Object::Notification { .. } => Kind::Notification,
+            Object::CNode { .. } => Kind::CNode,
+            Object::Tcb(_) => Kind::Tcb,
+            Object::Frame { .. } => Kind::Frame,
+            Object::PageTable { .. } => Kind::PageTable,
+            Object::PageDirectory { .. } => Kind::PageDirectory,
+            Object::Other(tag) => Kind::Other(tag),
+        }
+    }
+}
+
+struct Kernel {
+    objects: Vec<Object>,
+}
+
+struct Shared {
+    kernel: Mutex<Kernel>,
+    changed: Condvar,
+}
+
+struct Current {
+    shared: Arc<Shared>,
+    tcb: ObjId,
+}
+
+thread_local!(static CURRENT: RefCell<Option<Current>> = RefCell::new(None));
+thread_local!(static IPC_BUFFER: UnsafeCell<seL4_IPCBuffer> =
+              UnsafeCell::new(unsafe { mem::zeroed() }));
+thread_local!(static BOOT_INFO: UnsafeCell<seL4_BootInfo> =
+              UnsafeCell::new(unsafe { mem::zeroed() }));
+
+/// A message as a thread handed it to the kernel: the cptr it invoked, the
+/// label, and the message words and cptrs it put in its IPC buffer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Invocation {
+    pub dest: seL4_CPtr,
+    pub label: seL4_Word,
+    pub mrs: Vec<seL4_Word>,
+    pub caps: Vec<seL4_CPtr>,
+}
+
+thread_local!(static RECORDED: RefCell<Option<Vec<Invocation>>> = RefCell::new(None));
+thread_local!(static CANNED: RefCell<VecDeque<(seL4_Word, Vec<seL4_Word>)>> =
+              RefCell::new(VecDeque::new()));
+
+fn current() -> (Arc<Shared>, ObjId) {
+    CURRENT.with(|c| match *c.borrow() {
+        Some(ref cur) => (cur.shared.clone(), cur.tcb),
+        None => panic!("not a mock seL4 thread; call mock::boot first"),
+    })
+}
+
+/// The calling host thread's IPC buffer.
+pub fn ipc_buffer() -> *mut seL4_IPCBuffer {
+    IPC_BUFFER.with(|b| b.get())
+}
+
+/// Start a fresh kernel and make the calling host thread its initial
+/// thread.
+///
+/// The initial thread's CNode has `2^ROOT_CNODE_BITS` slots holding the
+/// usual initial caps, one untyped per entry of `UNTYPED_BITS`, and nothing
+/// else. The boot info is also passed to `init_boot_info`. Each call makes
+/// an independent kernel, so tests running in parallel do not interfere.
+///
+/// The boot info is kept per host thread and overwritten by the next `boot`
+/// on the same thread; it does not outlive the thread.
+pub fn boot() -> &'static seL4_BootInfo {
+    use seL4_Cap::*;
+
+    let mut k = Kernel { objects: Vec::new() };
+    let cnode = k.alloc(Object::CNode {
+        slots: iter::repeat(None).take(1 << ROOT_CNODE_BITS).collect(),
+    });
+    let vspace = k.alloc(Object::PageDirectory { tables: Vec::new() });
+    let mut tcb = Tcb::new();
+    tcb.priority = 255;
+    tcb.cspace = Some(Cap::new(cnode));
+    tcb.ipc_buffer = ipc_buffer() as usize as seL4_Word;
+    tcb.suspended = false;
+    let tcb = k.alloc(Object::Tcb(tcb));
+
+    /* No IOMMU, so seL4_CapIOSpace stays empty. */
+    let initial = [
+        (seL4_CapInitThreadTCB as usize, tcb),
+        (seL4_CapInitThreadCNode as usize, cnode),
+        (seL4_CapInitThreadVSpace as usize, vspace),
+        (seL4_CapIRQControl as usize, k.alloc(Object::Other(capTag::cap_irq_control_cap as u32))),
+        (seL4_CapASIDControl as usize, k.alloc(Object::Other(capTag::cap_asid_control_cap as u32))),
+        (seL4_CapInitThreadASIDPool as usize, k.alloc(Object::Other(capTag::cap_asid_pool_cap as u32))),
+        (seL4_CapIOPort as usize, k.alloc(Object::Other(capTag::cap_io_port_cap as u32))),
+        (seL4_CapBootInfoFrame as usize, k.alloc(Object::Frame { mapped: true })),
+        (seL4_CapInitThreadIPCBuffer as usize, k.alloc(Object::Frame { mapped: true })),
+        (seL4_CapDomain as usize, k.alloc(Object::Other(capTag::cap_domain_cap as u32))),
+    ];
+    for &(slot, obj) in initial.iter() {
+        k.slots(cnode)[slot] = Some(Cap::new(obj));
+    }
+
+    let mut bootinfo: seL4_BootInfo = unsafe { mem::zeroed() };
+    let first_free = seL4_CapDomain as seL4_Word + 1;
+    let mut paddr = 0;
+    for (i, &bits) in UNTYPED_BITS.iter().enumerate() {
+        let untyped = k.alloc(Object::Untyped { size_bits: bits as usize, used: 0 });
+        k.slots(cnode)[first_free as usize + i] = Some(Cap::new(untyped));
+        bootinfo.untypedPaddrList[i] = paddr;
+        bootinfo.untypedSizeBitsList[i] = bits;
+        paddr += 1 << bits;
+    }
+
+    let untyped_end = first_free + UNTYPED_BITS.len() as seL4_Word;
+    bootinfo.numNodes = 1;
+    bootinfo.ipcBuffer = ipc_buffer();
+    bootinfo.sharedFrames = seL4_SlotRegion { start: first_free, end: first_free };
+    bootinfo.userImageFrames = seL4_SlotRegion { start: first_free, end: first_free };
+    bootinfo.userImagePTs = seL4_SlotRegion { start: first_free, end: first_free };
+    bootinfo.untyped = seL4_SlotRegion { start: first_free, end: untyped_end };
+    bootinfo.empty = seL4_SlotRegion { start: untyped_end, end: 1 << ROOT_CNODE_BITS };
+    bootinfo.initThreadCNodeSizeBits = ROOT_CNODE_BITS as u8;
+
+    let shared = Arc::new(Shared { kernel: Mutex::new(k), changed: Condvar::new() });
+    CURRENT.with(|c| *c.borrow_mut() = Some(Current { shared: shared, tcb: tcb }));
+
+    let bootinfo: &'static seL4_BootInfo = BOOT_INFO.with(|b| unsafe {
+        *b.get() = bootinfo;
+        &*b.get()
+    });
+    init_boot_info(bootinfo);
+    bootinfo
+}
+
+/// Run `f` on a new host thread as the thread of `tcb`, a TCB cap in the
+/// caller's CSpace.
+///
+/// As with a real TCB, `f` only starts once the TCB is resumed, by
+/// `seL4_TCB_Resume` or a register write with `resume` set, and it needs a
+/// CSpace from `seL4_TCB_Configure` to make syscalls. The registers are
+/// kept but not used: `f` is the thread's code.
+pub fn spawn<F>(tcb: seL4_TCB, f: F) -> JoinHandle<()>
+    where F: FnOnce() + Send + 'static
+{
+    let (shared, me) = current();
+    let id = {
+        let k = shared.kernel.lock().unwrap();
+        match k.lookup(me, tcb) {
+            Ok(cap) if k.objects[cap.obj].kind() == Kind::Tcb => cap.obj,
+            _ => panic!("mock::spawn: {} is not a TCB cap", tcb),
+        }
+    };
+    ::std::thread::spawn(move || {
+        {
+            let k = shared.kernel.lock().unwrap();
+            drop(block_while(&shared, k, |k| k.tcb(id).suspended));
+        }
+        CURRENT.with(|c| *c.borrow_mut() = Some(Current { shared: shared, tcb: id }));
+        f();
+    })
+}
+
+/// Start recording the messages the calling thread sends with Send, NBSend
+/// and Call, whether to an endpoint or as an invocation.
+pub fn record() {
+    RECORDED.with(|r| *r.borrow_mut() = Some(Vec::new()));
+}
+
+/// Stop recording and return what the calling thread sent since `record`,
+/// oldest first.
+pub fn recorded() -> Vec<Invocation> {
+    RECORDED.with(|r| r.borrow_mut().take().unwrap_or_else(Vec::new))
+}
+
+/// Answer the calling thread's next Call with `label` and `mrs`, whatever
+/// it invokes, without passing the message to the model. Replies queue up
+/// if this is called more than once.
+pub fn canned_reply(label: seL4_Word, mrs: &[seL4_Word]) {
+    CANNED.with(|c| c.borrow_mut().push_back((label, mrs.to_vec())));
+}
+
+/// What the model knows about a thread, for checking in tests.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ThreadInfo {
+    pub regs: seL4_UserContext,
+    pub priority: u8,
+    pub fault_ep: seL4_CPtr,
+    pub ipc_buffer: seL4_Word,
+    pub name: String,
+    pub suspended: bool,
+}
+
+/// The state of the thread of `tcb`, a TCB cap in the caller's CSpace.
+pub fn thread_info(tcb: seL4_TCB) -> Option<ThreadInfo> {
+    let (shared, me) = current();
+    let mut k = shared.kernel.lock().unwrap();
+    let id = match k.lookup(me, tcb) {
+        Ok(cap) if k.objects[cap.obj].kind() == Kind::Tcb => cap.obj,
+        _ => return None,
+    };
+    let t = k.tcb(id);
+    Some(ThreadInfo {
+        regs: t.regs,
+        priority: t.priority,
+        fault_ep: t.fault_ep,
+        ipc_buffer: t.ipc_buffer,
+        name: t.name.clone(),
+        suspended: t.suspended,
+    })
+}
+
+fn block_while<'a, F>(shared: &'a Shared, mut k: MutexGuard<'a, Kernel>, mut cond: F)
+                      -> MutexGuard<'a, Kernel>
+    where F: FnMut(&mut Kernel) -> bool
+{
+    while cond(&mut k) {
+        k = shared.changed.wait(k).unwrap();
+    }
+    k
+}
+
+/* Take the kernel lock on syscall entry, stopping here while suspended. */
+fn enter<'a>(shared: &'a Shared, me: ObjId) -> MutexGuard<'a, Kernel> {
+    let k = shared.kernel.lock().unwrap();
+    block_while(shared, k, |k| k.tcb(me).suspended)
+}
+
+fn error_info(err: seL4_Error) -> seL4_MessageInfo {
+    seL4_MessageInfo::new(err as seL4_Word, 0, 0, 0)
+}
+
+/* Read the outgoing message from the caller's IPC buffer. */
+unsafe fn outgoing(k: &Kernel, me: ObjId, info: &seL4_MessageInfo) -> Message {
+    let buffer = &*ipc_buffer();
+    let len = cmp::min(info.get_length() as usize, seL4_MsgMaxLength);
+    let ncaps = cmp::min(info.get_extraCaps() as usize, seL4_MsgMaxExtraCaps);
+    Message {
+        label: info.get_label(),
+        mrs: buffer.msg[..len].to_vec(),
+        caps: buffer.caps_or_badges[..ncaps].iter().map(|&c| k.lookup(me, c).ok()).collect(),
+        badge: 0,
+    }
+}
+
+/* Log an outgoing message if the caller is recording. */
+unsafe fn record_sent(dest: seL4_CPtr, info: &seL4_MessageInfo) {
+    RECORDED.with(|r| {
+        if let Some(ref mut log) = *r.borrow_mut() {
+            let buffer = &*ipc_buffer();
+            let len = cmp::min(info.get_length() as usize, seL4_MsgMaxLength);
+            let ncaps = cmp::min(info.get_extraCaps() as usize, seL4_MsgMaxExtraCaps);
+            log.push(Invocation {
+                dest: dest,
+                label: info.get_label(),
+                mrs: buffer.msg[..len].to_vec(),
+                caps: buffer.caps_or_badges[..ncaps].to_vec(),
+            });
+        }
+    });
+}
+
+/* Write a received message into the caller's IPC buffer. `ep` is the
+ * endpoint it arrived on, for unwrapping. */
+unsafe fn deliver(k: &mut Kernel, me: ObjId, ep: Option<ObjId>, msg: Message) -> seL4_MessageInfo {
+    let buffer = &mut *ipc_buffer();
+    for (i, &w) in msg.mrs.iter().enumerate() {
+        buffer.msg[i] = w;
+    }
+
+    let mut unwrapped = 0;
+    let mut count = 0;
+    let mut slot_used = false;
+    for (i, cap) in msg.caps.iter().enumerate() {
+        let cap = match *cap {
+            Some(cap) => cap,
+            None => break,
+        };
+        if Some(cap.obj) == ep {
+            buffer.caps_or_badges[i] = cap.badge;
+            unwrapped |= 1 << i;
+        } else {
+            if slot_used {
+                break;
+            }
+            match k.receive_slot(me, buffer) {
+                Some((cnode, index)) => k.slots(cnode)[index] = Some(cap),
+                None => break,
+            }
+            slot_used = true;
+        }
+        count = i + 1;
+    }
+
+    seL4_MessageInfo::new(msg.label, unwrapped, count as seL4_Word, msg.mrs.len() as seL4_Word)
+}
+
+pub unsafe fn send(dest: seL4_CPtr, info: seL4_MessageInfo, block: bool) {
+    let (shared, me) = current();
+    let mut k = enter(&shared, me);
+    record_sent(dest, &info);
+    let cap = match k.lookup(me, dest) {
+        Ok(cap) => cap,
+        /* The kernel drops a failed NBSend, but faults a blocking Send,
+         * which the model cannot raise. */
+        Err(_) if block => {
+            drop(k);
+            panic!("seL4_Send to cptr {:#x}, which holds no cap", dest);
+        }
+        Err(_) => return,
+    };
+    let mut msg = outgoing(&k, me, &info);
+    match k.objects[cap.obj].kind() {
+        Kind::Endpoint => {
+            if !block && k.receivers(cap.obj) == 0 {
+                return;
+            }
+            msg.badge = cap.badge;
+            k.endpoint_queue(cap.obj).push_back(Pending { sender: me, msg: msg, call: false });
+            k.tcb(me).sending = true;
+            shared.changed.notify_all();
+            drop(block_while(&shared, k, |k| k.tcb(me).sending || k.tcb(me).suspended));
+        }
+        Kind::Notification => {
+            let word = msg.mrs.get(0).cloned().unwrap_or(0);
+            k.signal(cap, word);
+            shared.changed.notify_all();
+        }
+        _ => {
+            let _ = k.invoke(cap, &msg);
+            shared.changed.notify_all();
+        }
+    }
+}
+
+pub unsafe fn call(dest: seL4_CPtr, info: seL4_MessageInfo) -> seL4_MessageInfo {
+    let (shared, me) = current();
+    let mut k = enter(&shared, me);
+    record_sent(dest, &info);
+    if let Some((label, mrs)) = CANNED.with(|c| c.borrow_mut().pop_front()) {
+        let buffer = &mut *ipc_buffer();
+        for (i, &w) in mrs.iter().enumerate() {
+            buffer.msg[i] = w;
+        }
+        return seL4_MessageInfo::new(label, 0, 0, mrs.len() as seL4_Word);
+    }
+    let cap = match k.lookup(me, dest) {
+        Ok(cap) => cap,
+        Err(_) => return error_info(seL4_Error::seL4_InvalidCapability),
+    };
+    let mut msg = outgoing(&k, me, &info);
+    match k.objects[cap.obj].kind() {
+        Kind::Endpoint => {
+            msg.badge = cap.badge;
+            k.endpoint_queue(cap.obj).push_back(Pending { sender: me, msg: msg, call: true });
+            k.tcb(me).sending = true;
+            k.tcb(me).reply = None;
+            shared.changed.notify_all();
+            let mut k = block_while(&shared, k, |k| {
+                let t = k.tcb(me);
+                t.sending || t.reply.is_none() || t.suspended
+            });
+            let reply = k.tcb(me).reply.take().unwrap();
+            deliver(&mut k, me, None, reply)
+        }
+        Kind::Notification => {
+            let word = msg.mrs.get(0).cloned().unwrap_or(0);
+            k.signal(cap, word);
+            shared.changed.notify_all();
+            seL4_MessageInfo::new(0, 0, 0, 0)
+        }
+        _ => {
+            let result = k.invoke(cap, &msg);
+            shared.changed.notify_all();
+            let k = block_while(&shared, k, |k| k.tcb(me).suspended);
+            drop(k);
+            match result {
+                Ok(mrs) => {
+                    let buffer = &mut *ipc_buffer();
+                    for (i, &w) in mrs.iter().enumerate() {
+                        buffer.msg[i] = w;
+                    }
+                    seL4_MessageInfo::new(0, 0, 0, mrs.len() as seL4_Word)
+                }
+                Err(err) => error_info(err),
+            }
+        }
+    }
+}
+
+pub unsafe fn reply(info: seL4_MessageInfo) {
+    let (shared, me) = current();
+    let mut k = enter(&shared, me);
+    if let Some(caller) = k.tcb(me).caller.take() {
+        let msg = outgoing(&k, me, &info);
+        k.tcb(caller).reply = Some(msg);
+        shared.changed.notify_all();
+    }
+}
+
+pub unsafe fn wait(src: seL4_CPtr) -> (seL4_MessageInfo, seL4_Word) {
+    let (shared, me) = current();
+    let mut k = enter(&shared, me);
+    let cap = match k.lookup(me, src) {
+        Ok(cap) => cap,
+        Err(_) => return (error_info(seL4_Error::seL4_InvalidCapability), 0),
+    };
+    match k.objects[cap.obj].kind() {
+        Kind::Endpoint => {
+            let ep = cap.obj;
+            k.set_receivers(ep, 1);
+            shared.changed.notify_all();
+            let mut k = block_while(&shared, k, |k| {
+                k.endpoint_queue(ep).is_empty() || k.tcb(me).suspended
+            });
+            k.set_receivers(ep, -1);
+            let pending = k.endpoint_queue(ep).pop_front().unwrap();
+            k.tcb(pending.sender).sending = false;
+            if pending.call {
+                k.tcb(me).caller = Some(pending.sender);
+            }
+            let badge = pending.msg.badge;
+            let info = deliver(&mut k, me, Some(ep), pending.msg);
+            shared.changed.notify_all();
+            (info, badge)
+        }
+        Kind::Notification => {
+            let aep = cap.obj;
+            let mut k = block_while(&shared, k, |k| {
+                !k.notification_pending(aep) || k.tcb(me).suspended
+            });
+            let (badge, data) = k.take_notification(aep);
+            (*ipc_buffer()).msg[0] = data;
+            (seL4_MessageInfo::new(0, 0, 0, 1), badge)
+        }
+        _ => (error_info(seL4_Error::seL4_InvalidCapability), 0),
+    }
+}
+
+pub unsafe fn notify(dest: seL4_CPtr, msg: seL4_Word) {
+    let (shared, me) = current();
+    let mut k = enter(&shared, me);
+    if let Ok(cap) = k.lookup(me, dest) {
+        if k.objects[cap.obj].kind() == Kind::Notification {
+            k.signal(cap, msg);
+            shared.changed.notify_all();
+        }
+    }
+}
+
+pub fn yield_now() {
+    let (shared, me) = current();
+    drop(enter(&shared, me));
+    ::std::thread::yield_now();
+}
+
+pub fn debug_put_char(c: u8) {
+    let _ = io::stderr().write_all(&[c]);
+}
+
+/// The debug cap tag of the cap in `cptr`, 0 for an empty slot.
+pub fn cap_identify(cptr: seL4_CPtr) -> u32 {
+    let (shared, me) = current();
+    let k = enter(&shared, me);
+    match k.lookup(me, cptr) {
+        Ok(cap) => match k.objects[cap.obj].kind() {
+            Kind::Untyped => capTag::cap_untyped_cap as u32,
+            Kind::Endpoint => capTag::cap_endpoint_cap as u32,
+            Kind::Notification => capTag::cap_async_endpoint_cap as u32,
+            Kind::CNode => capTag::cap_cnode_cap as u32,
+            Kind::Tcb => capTag::cap_thread_cap as u32,
+            Kind::Frame => capTag::cap_frame_cap as u32,
+            Kind::PageTable => capTag::cap_page_table_cap as u32,
+            Kind::PageDirectory => capTag::cap_page_directory_cap as u32,
+            Kind::Other(tag) => tag,
+        },
+        Err(_) => capTag::cap_null_cap as u32,
+    }
+}
+
+/* The name is NUL-terminated at the start of the message registers. */
+pub unsafe fn name_thread(tcb: seL4_CPtr) {
+    let (shared, me) = current();
+    let mut k = enter(&shared, me);
+    let bytes = (*ipc_buffer()).msg.as_ptr() as *const u8;
+    let mut name = Vec::new();
+    for i in 0..seL4_MaxThreadNameLength {
+        match *bytes.offset(i as isize) {
+            0 => break,
+            b => name.push(b),
+        }
+    }
+    let name = String::from_utf8_lossy(&name).into_owned();
+    if let Ok(cap) = k.lookup(me, tcb) {
+        if k.objects[cap.obj].kind() == Kind::Tcb {
+            k.tcb(cap.obj).name = name;
+        }
+    }
+}
+
+/* Invocation arguments, failing like the kernel when the message is short. */
+
+fn arg(msg: &Message, i: usize) -> Result<seL4_Word, seL4_Error> {
+    msg.mrs.get(i).cloned().ok_or(seL4_Error::seL4_TruncatedMessage)
+}
+
+fn cap_arg(msg: &Message, i: usize) -> Result<Cap, seL4_Error> {
+    match msg.caps.get(i) {
+        Some(&Some(cap)) => Ok(cap),
+        Some(&None) => Err(seL4_Error::seL4_FailedLookup),
+        None => Err(seL4_Error::seL4_TruncatedMessage),
+    }
+}
+
+impl Kernel {
+    fn alloc(&mut self, obj: Object) -> ObjId {
+        self.objects.push(obj);
+        self.objects.len() - 1
+    }
+
+    fn tcb(&mut self, id: ObjId) -> &mut Tcb {
+        match self.objects[id] {
+            Object::Tcb(ref mut tcb) => tcb,
+            _ => panic!("object {} is not a TCB", id),
+        }
+    }
+
+    fn slots(&mut self, id: ObjId) -> &mut Vec<Option<Cap>> {
+        match self.objects[id] {
+            Object::CNode { ref mut slots } => slots,
+            _ => panic!("object {} is not a CNode", id),
+        }
+    }
+
+    fn endpoint_queue(&mut self, id: ObjId) -> &mut VecDeque<Pending> {
+        match self.objects[id] {
+            Object::Endpoint { ref mut queue, .. } => queue,
+            _ => panic!("object {} is not an endpoint", id),
+        }
+    }
+
+    fn receivers(&self, id: ObjId) -> usize {
+        match self.objects[id] {
+            Object::Endpoint { receivers, .. } => receivers,
+            _ => 0,
+        }
+    }
+
+    fn set_receivers(&mut self, id: ObjId, delta: isize) {
+        if let Object::Endpoint { ref mut receivers, .. } = self.objects[id] {
+            *receivers = (*receivers as isize + delta) as usize;
+        }
+    }
+
+    fn signal(&mut self, cap: Cap, msg: seL4_Word) {
+        if let Object::Notification { ref mut badge, ref mut data, ref mut pending } = self.objects[cap.obj] {
+            *badge |= cap.badge;
+            *data |= msg;
+            *pending = true;
+        }
+    }
+
+    fn notification_pending(&self, id: ObjId) -> bool {
+        match self.objects[id] {
+            Object::Notification { pending, .. } => pending,
+            _ => false,
+        }
+    }
+
+    fn take_notification(&mut self, id: ObjId) -> (seL4_Word, seL4_Word) {
+        match self.objects[id] {
+            Object::Notification { ref mut badge, ref mut data, ref mut pending } => {
+                *pending = false;
+                (mem::replace(badge, 0), mem::replace(data, 0))
+            }
+            _ => (0, 0),
+        }
+    }
+
+    /* The root CNode of `thread`'s CSpace. */
+    fn cspace(&self, thread: ObjId) -> Option<ObjId> {
+        match self.objects[thread] {
+            Object::Tcb(ref tcb) => tcb.cspace.map(|c| c.obj),
+            _ => None,
+        }
+    }
+
+    fn slot(&self, cnode: ObjId, index: seL4_Word) -> Result<Option<Cap>, seL4_Error> {
+        match self.objects[cnode] {
+            Object::CNode { ref slots } => {
+                slots.get(index as usize).cloned().ok_or(seL4_Error::seL4_RangeError)
+            }
+            _ => Err(seL4_Error::seL4_FailedLookup),
+        }
+    }
+
+    fn lookup(&self, thread: ObjId, cptr: seL4_CPtr) -> Result<Cap, seL4_Error> {
+        let root = try!(self.cspace(thread).ok_or(seL4_Error::seL4_FailedLookup));
+        match self.slot(root, cptr) {
+            Ok(Some(cap)) => Ok(cap),
+            _ => Err(seL4_Error::seL4_FailedLookup),
+        }
+    }
+
+    /* Where an incoming cap goes: an empty slot named by the receive path. */
+    fn receive_slot(&self, thread: ObjId, buffer: &seL4_IPCBuffer) -> Option<(ObjId, usize)> {
+        let cnode = match self.lookup(thread, buffer.receiveCNode) {
+            Ok(cap) if self.objects[cap.obj].kind() == Kind::CNode => cap.obj,
+            _ => return None,
+        };
+        match self.slot(cnode, buffer.receiveIndex) {
+            Ok(None) => Some((cnode, buffer.receiveIndex as usize)),
+            _ => None,
+        }
+    }
+
+    /* A CNode named by a cap and an index and depth below it. */
+    fn cnode_at(&self, root: Cap, index: seL4_Word, depth: seL4_Word) -> Result<ObjId, seL4_Error> {
+        if self.objects[root.obj].kind() != Kind::CNode {
+            return Err(seL4_Error::seL4_FailedLookup);
+        }
+        if depth == 0 {
+            return Ok(root.obj);
+        }
+        match try!(self.slot(root.obj, index)) {
+            Some(cap) if self.objects[cap.obj].kind() == Kind::CNode => Ok(cap.obj),
+            _ => Err(seL4_Error::seL4_FailedLookup),
+        }
+    }
+
+    fn invoke(&mut self, cap: Cap, msg: &Message) -> Result<Vec<seL4_Word>, seL4_Error> {
+        use InvocationLabel::*;
+        let is = |label: InvocationLabel| msg.label == label as seL4_Word;
+
+        match self.objects[cap.obj].kind() {
+            Kind::Untyped if is(UntypedRetype) => self.retype(cap.obj, msg),
+            Kind::Tcb if is(TCBConfigure) => self.tcb_configure(cap.obj, msg),
+            Kind::Tcb if is(TCBReadRegisters) => self.tcb_read_registers(cap.obj, msg),
+            Kind::Tcb if is(TCBWriteRegisters) => self.tcb_write_registers(cap.obj, msg),
+            Kind::Tcb if is(TCBSetPriority) => {
+                self.tcb(cap.obj).priority = try!(arg(msg, 0)) as u8;
+                Ok(Vec::new())
+            }
+            Kind::Tcb if is(TCBSetIPCBuffer) => {
+                let buffer = try!(arg(msg, 0));
+                try!(self.expect(try!(cap_arg(msg, 0)), Kind::Frame));
+                self.tcb(cap.obj).ipc_buffer = buffer;
+                Ok(Vec::new())
+            }
+            Kind::Tcb if is(TCBSuspend) => {
+                self.tcb(cap.obj).suspended = true;
+                Ok(Vec::new())
+            }
+            Kind::Tcb if is(TCBResume) => {
+                self.tcb(cap.obj).suspended = false;
+                Ok(Vec::new())
+            }
+            Kind::CNode if is(CNodeCopy) => self.cnode_copy(cap.obj, msg, false),
+            Kind::CNode if is(CNodeMint) => self.cnode_copy(cap.obj, msg, true),
+            Kind::CNode if is(CNodeMove) => self.cnode_move(cap.obj, msg),
+            Kind::CNode if is(CNodeDelete) => {
+                let index = try!(arg(msg, 0));
+                try!(self.slot(cap.obj, index));
+                self.slots(cap.obj)[index as usize] = None;
+                Ok(Vec::new())
+            }
+            Kind::CNode if is(CNodeRevoke) => Ok(Vec::new()),
+            Kind::Frame if is(IA32PageMap) => self.page_map(cap.obj, msg),
+            Kind::Frame if is(IA32PageUnmap) => {
+                self.objects[cap.obj] = Object::Frame { mapped: false };
+                Ok(Vec::new())
+            }
+            Kind::PageTable if is(IA32PageTableMap) => self.page_table_map(cap.obj, msg),
+            _ => Err(seL4_Error::seL4_IllegalOperation),
+        }
+    }
+
+    fn expect(&self, cap: Cap, kind: Kind) -> Result<Cap, seL4_Error> {
+        if self.objects[cap.obj].kind() == kind {
+            Ok(cap)
+        } else {
+            Err(seL4_Error::seL4_IllegalOperation)
+        }
+    }
+
+    /* seL4_Untyped_Retype(type, size_bits, root, node_index, node_depth,
+     * node_offset, num_objects) */
+    fn retype(&mut self, untyped: ObjId, msg: &Message) -> Result<Vec<seL4_Word>, seL4_Error> {
+        use seL4_ObjectType::*;
+
+        let type_ = try!(arg(msg, 0));
+        let size_bits = try!(arg(msg, 1)) as usize;
+        let root = try!(cap_arg(msg, 0));
+        let node_index = try!(arg(msg, 2));
+        let node_depth = try!(arg(msg, 3));
+        let node_offset = try!(arg(msg, 4)) as usize;
+        let num = try!(arg(msg, 5)) as usize;
+
+        let bits = match type_ {
+            t if t == seL4_UntypedObject as seL4_Word => size_bits,
+            t if t == seL4_TCBObject as seL4_Word => seL4_TCBBits,
+            t if t == seL4_EndpointObject as seL4_Word => seL4_EndpointBits,
+            t if t == seL4_AsyncEndpointObject as seL4_Word => seL4_EndpointBits,
+            t if t == seL4_CapTableObject as seL4_Word => size_bits + seL4_SlotBits,
+            t if t == seL4_IA32_4K as seL4_Word => seL4_PageBits,
+            t if t == seL4_IA32_4M as seL4_Word => 22,
+            t if t == seL4_IA32_PageTableObject as seL4_Word => seL4_PageTableBits,
+            t if t == seL4_IA32_PageDirectoryObject as seL4_Word => seL4_PageDirBits,
+            _ => return Err(seL4_Error::seL4_InvalidArgument),
+        };
+
+        let cnode = try!(self.cnode_at(root, node_index, node_depth));
+        {
+            let slots = self.slots(cnode);
+            if node_offset + num > slots.len() {
+                return Err(seL4_Error::seL4_RangeError);
+            }
+            if slots[node_offset..node_offset + num].iter().any(|s| s.is_some()) {
+                return Err(seL4_Error::seL4_DeleteFirst);
+            }
+        }
+
+        let start = match self.objects[untyped] {
+            Object::Untyped { size_bits: ut_bits, used } => {
+                let start = (used + (1 << bits) - 1) & !((1 << bits) - 1);
+                if bits > ut_bits || start + num * (1 << bits) > 1 << ut_bits {
+                    return Err(seL4_Error::seL4_NotEnoughMemory);
+                }
+                start
+            }
+            _ => unreachable!(),
+        };
+        if let Object::Untyped { ref mut used, .. } = self.objects[untyped] {
+            *used = start + num * (1 << bits);
+        }
+
+        for i in 0..num {
+            let obj = match type_ {
+                t if t == seL4_UntypedObject as seL4_Word => Object::Untyped { size_bits: size_bits, used: 0 },
+                t if t == seL4_TCBObject as seL4_Word => Object::Tcb(Tcb::new()),
+                t if t == seL4_EndpointObject as seL4_Word => {
+                    Object::Endpoint { queue: VecDeque::new(), receivers: 0 }
+                }
+                t if t == seL4_AsyncEndpointObject as seL4_Word => {
+                    Object::Notification { badge: 0, data: 0, pending: false }
+                }
+                t if t == seL4_CapTableObject as seL4_Word => Object::CNode {
+                    slots: iter::repeat(None).take(1 << size_bits).collect(),
+                },
+                t if t == seL4_IA32_PageTableObject as seL4_Word => Object::PageTable { mapped: false },
+                t if t == seL4_IA32_PageDirectoryObject as seL4_Word => {
+                    Object::PageDirectory { tables: Vec::new() }
+                }
+                _ => Object::Frame { mapped: false },
+            };
+            let obj = self.alloc(obj);
+            self.slots(cnode)[node_offset + i] = Some(Cap::new(obj));
+        }
+        Ok(Vec::new())
+    }
+
+    /* seL4_TCB_Configure(fault_ep, priority, cspace_root, cspace_root_data,
+     * vspace_root, vspace_root_data, buffer, bufferFrame) */
+    fn tcb_configure(&mut self, tcb: ObjId, msg: &Message) -> Result<Vec<seL4_Word>, seL4_Error> {
+        let fault_ep = try!(arg(msg, 0));
+        let priority = try!(arg(msg, 1)) as u8;
+        let buffer = try!(arg(msg, 4));
+        let cspace = try!(self.expect(try!(cap_arg(msg, 0)), Kind::CNode));
+        try!(self.expect(try!(cap_arg(msg, 1)), Kind::PageDirectory));
+        /* A null buffer frame leaves the thread without an IPC buffer. */
+        if let Ok(frame) = cap_arg(msg, 2) {
+            try!(self.expect(frame, Kind::Frame));
+        }
+
+        let t = self.tcb(tcb);
+        t.fault_ep = fault_ep;
+        t.priority = priority;
+        t.cspace = Some(cspace);
+        t.ipc_buffer = buffer;
+        Ok(Vec::new())
+    }
+
+    /* seL4_TCB_ReadRegisters(suspend_source, arch_flags, count) */
+    fn tcb_read_registers(&mut self, tcb: ObjId, msg: &Message) -> Result<Vec<seL4_Word>, seL4_Error> {
+        let suspend = try!(arg(msg, 0)) & 0xff != 0;
+        let count = try!(arg(msg, 1)) as usize;
+        if count > seL4_UserContextWords {
+            return Err(seL4_Error::seL4_RangeError);
+        }
+        let t = self.tcb(tcb);
+        if suspend {
+            t.suspended = true;
+        }
+        Ok(t.regs.as_words()[..count].to_vec())
+    }
+
+    /* seL4_TCB_WriteRegisters(resume_target, arch_flags, count, regs) */
+    fn tcb_write_registers(&mut self, tcb: ObjId, msg: &Message) -> Result<Vec<seL4_Word>, seL4_Error> {
+        let resume = try!(arg(msg, 0)) & 0xff != 0;
+        let count = try!(arg(msg, 1)) as usize;
+        if count > seL4_UserContextWords {
+            return Err(seL4_Error::seL4_RangeError);
+        }
+        if msg.mrs.len() < 2 + count {
+            return Err(seL4_Error::seL4_TruncatedMessage);
+        }
+        let t = self.tcb(tcb);
+        for (r, &w) in t.regs.as_words_mut().iter_mut().zip(msg.mrs[2..2 + count].iter()) {
+            *r = w;
+        }
+        if resume {
+            t.suspended = false;
+        }
+        Ok(Vec::new())
+    }
+
+    /* seL4_CNode_Copy(dest_index, dest_depth, src_root, src_index, src_depth,
+     * rights), and Mint with a badge after the rights. */
+    fn cnode_copy(&mut self, dest: ObjId, msg: &Message, mint: bool)
+                  -> Result<Vec<seL4_Word>, seL4_Error> {
+        let dest_index = try!(arg(msg, 0));
+        let src_root = try!(self.expect(try!(cap_arg(msg, 0)), Kind::CNode));
+        let src_index = try!(arg(msg, 2));
+        let rights = try!(arg(msg, 4));
+
+        let mut cap = match try!(self.slot(src_root.obj, src_index)) {
+            Some(cap) => cap,
+            None => return Err(seL4_Error::seL4_FailedLookup),
+        };
+        if try!(self.slot(dest, dest_index)).is_some() {
+            return Err(seL4_Error::seL4_DeleteFirst);
+        }
+        cap.rights &= rights;
+        if mint {
+            let badge = try!(arg(msg, 5)) & BADGE_MASK;
+            if badge != 0 {
+                if cap.badge != 0 {
+                    return Err(seL4_Error::seL4_IllegalOperation);
+                }
+                cap.badge = badge;
+            }
+        }
+        self.slots(dest)[dest_index as usize] = Some(cap);
+        Ok(Vec::new())
+    }
+
+    /* seL4_CNode_Move(dest_index, dest_depth, src_root, src_index, src_depth) */
+    fn cnode_move(&mut self, dest: ObjId, msg: &Message) -> Result<Vec<seL4_Word>, seL4_Error> {
+        let dest_index = try!(arg(msg, 0));
+        let src_root = try!(self.expect(try!(cap_arg(msg, 0)), Kind::CNode));
+        let src_index = try!(arg(msg, 2));
+
+        let cap = match try!(self.slot(src_root.obj, src_index)) {
+            Some(cap) => cap,
+            None => return Err(seL4_Error::seL4_FailedLookup),
+        };
+        if try!(self.slot(dest, dest_index)).is_some() {
+            return Err(seL4_Error::seL4_DeleteFirst);
+        }
+        self.slots(src_root.obj)[src_index as usize] = None;
+        self.slots(dest)[dest_index as usize] = Some(cap);
+        Ok(Vec::new())
+    }
+
+    /* seL4_IA32_Page_Map(pd, vaddr, rights, attr) */
+    fn page_map(&mut self, frame: ObjId, msg: &Message) -> Result<Vec<seL4_Word>, seL4_Error> {
+        let pd = try!(self.expect(try!(cap_arg(msg, 0)), Kind::PageDirectory));
+        let vaddr = try!(arg(msg, 0));
+        if vaddr & ((1 << seL4_PageBits) - 1) != 0 {
+            return Err(seL4_Error::seL4_AlignmentError);
+        }
+        if let Object::Frame { mapped: true } = self.objects[frame] {
+            return Err(seL4_Error::seL4_InvalidCapability);
+        }
+        match self.objects[pd.obj] {
+            Object::PageDirectory { ref tables } if tables.contains(&(vaddr >> 22)) => { }
+            _ => return Err(seL4_Error::seL4_FailedLookup),
+        }
+        self.objects[frame] = Object::Frame { mapped: true };
+        Ok(Vec::new())
+    }
+
+    /* seL4_IA32_PageTable_Map(pd, vaddr, attr) */
+    fn page_table_map(&mut self, pt: ObjId, msg: &Message) -> Result<Vec<seL4_Word>, seL4_Error> {
+        let pd = try!(self.expect(try!(cap_arg(msg, 0)), Kind::PageDirectory));
+        let vaddr = try!(arg(msg, 0));
+        if let Object::PageTable { mapped: true } = self.objects[pt] {
+            return Err(seL4_Error::seL4_InvalidCapability);
+        }
+        match self.objects[pd.obj] {
+            Object::PageDirectory { ref mut tables } => {
+                if tables.contains(&(vaddr >> 22)) {
+                    return Err(seL4_Error::seL4_DeleteFirst);
+                }
+                tables.push(vaddr >> 22);
+            }
+            _ => unreachable!(),
+        }
+        self.objects[pt] = Object::PageTable { mapped: true };
+        Ok(Vec::new())
+    }
+}
@@ -0,0 +1,71 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+//! Panic handling for root tasks.
+//!
+//! With the `panic_handler` feature, the crate provides the `panic_fmt` and
+//! `eh_personality` lang items. A panic prints its location and message on
+//! the kernel debug console (when `SEL4_DEBUG` is enabled) and then goes
+//! down the `fatal` path, unless it happened in an isolated test (see
+//! `testing`), which fails just that test.
+//!
+//! The feature does nothing with `mock`, as std provides the lang items there.
+
+use core::fmt;
+use super::*;
+
+#[lang = "panic_fmt"]
+#[no_mangle]
+pub extern fn rust_begin_unwind(msg: fmt::Arguments, file: &'static str, line: u32) -> ! {
+    test_panicked(msg, file, line);
+    report(msg, file, line);
+    fatal()
+}
+
+#[lang = "eh_personality"]
+#[no_mangle]
+pub extern fn rust_eh_personality() { }
+
+#[cfg(all(feature = "runtime", feature = "SEL4_DEBUG"))]
+fn test_panicked(msg: fmt::Arguments, file: &'static str, line: u32) {
+    ::testing::panicked(msg, file, line);
+}
+
+#[cfg(not(all(feature = "runtime", feature = "SEL4_DEBUG")))]
+fn test_panicked(_msg: fmt::Arguments, _file: &'static str, _line: u32) { }
+
+#[cfg(feature = "SEL4_DEBUG")]
+fn report(msg: fmt::Arguments, file: &'static str, line: u32) {
+    debug_println!("panicked at '{}', {}:{}", msg, file, line);
+}
+
+#[cfg(not(feature = "SEL4_DEBUG"))]
+fn report(_msg: fmt::Arguments, _file: &'static str, _line: u32) { }
+
+/// Stop after an unrecoverable error.
+///
+/// In debug kernels this halts the whole system so the console output stays
+/// where it is. Otherwise the initial thread suspends itself.
+pub fn fatal() -> ! {
+    unsafe {
+        halt();
+    }
+    loop { }
+}
+
+#[cfg(feature = "SEL4_DEBUG")]
+unsafe fn halt() {
+    seL4_DebugHalt();
+}
+
+#[cfg(not(feature = "SEL4_DEBUG"))]
+unsafe fn halt() {
+    seL4_TCB_Suspend(seL4_Cap::seL4_CapInitThreadTCB as seL4_TCB);
+}
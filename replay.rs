@@ -0,0 +1,282 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+//! Replay of a traced run against the mock kernel.
+//!
+//! A component built with the `trace` feature and run on seL4 after
+//! `trace::stream(true)` prints every syscall it makes and what came back.
+//! `parse` reads those lines out of the captured serial log, and `run` runs
+//! the component on the host with each syscall answered from the capture
+//! instead of by the mock kernel, checking that it makes the same syscalls
+//! with the same messages in the same order. As every result comes from the
+//! capture, the replay is deterministic and needs no `mock::boot`.
+//!
+//! Only what the trace records is replayed: the first `TRACE_MRS` message
+//! registers of each message, and no caps. A capture holds the syscalls of
+//! every thread in the address space, in the order they were made, and a
+//! replay runs the component on one host thread, so capture components
+//! that make their syscalls from a single thread.
+
+use core::{cmp, fmt};
+use core::str::SplitWhitespace;
+use std::boxed::Box;
+use std::cell::RefCell;
+use std::panic;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::vec::Vec;
+use trace::{Entry, Info, Outcome, EXPORT_PREFIX, TRACE_MRS};
+use super::*;
+
+/// A line of a capture that `parse` could not read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// Line number, counting from 1.
+    pub line: usize,
+}
+
+macro_rules! some {
+    ($e:expr) => (match $e { Some(x) => x, None => return None })
+}
+
+struct Fields<'a>(SplitWhitespace<'a>);
+
+impl<'a> Fields<'a> {
+    fn word(&mut self) -> Option<&'a str> {
+        self.0.next()
+    }
+
+    fn hex(&mut self) -> Option<seL4_Word> {
+        self.word().and_then(|w| seL4_Word::from_str_radix(w, 16).ok())
+    }
+
+    fn info(&mut self) -> Option<Info> {
+        Some(Info {
+            label: some!(self.hex()),
+            caps_unwrapped: some!(self.hex()),
+            extra_caps: some!(self.hex()),
+            length: some!(self.hex()),
+        })
+    }
+
+    fn mrs(&mut self) -> Option<[seL4_Word; TRACE_MRS]> {
+        let mut mrs = [0; TRACE_MRS];
+        for mr in mrs.iter_mut() {
+            *mr = some!(self.hex());
+        }
+        Some(mrs)
+    }
+}
+
+fn syscall_named(name: &str) -> Option<SyscallId> {
+    Some(match name {
+        "Call" => SyscallId::Call,
+        "ReplyWait" => SyscallId::ReplyWait,
+        "Send" => SyscallId::Send,
+        "NBSend" => SyscallId::NBSend,
+        "Reply" => SyscallId::Reply,
+        "Wait" => SyscallId::Wait,
+        "Yield" => SyscallId::Yield,
+        #[cfg(feature = "SEL4_DEBUG")]
+        "DebugHalt" => SyscallId::DebugHalt,
+        #[cfg(feature = "SEL4_DEBUG")]
+        "DebugSnapshot" => SyscallId::DebugSnapshot,
+        #[cfg(feature = "SEL4_DEBUG")]
+        "DebugCapIdentify" => SyscallId::DebugCapIdentify,
+        #[cfg(feature = "SEL4_DEBUG")]
+        "DebugNameThread" => SyscallId::DebugNameThread,
+        #[cfg(feature = "SEL4_DANGEROUS_CODE_INJECTION")]
+        "DebugRun" => SyscallId::DebugRun,
+        #[cfg(feature = "SEL4_CONFIG_BENCHMARK")]
+        "BenchmarkResetLog" => SyscallId::BenchmarkResetLog,
+        #[cfg(feature = "SEL4_CONFIG_BENCHMARK")]
+        "BenchmarkDumpLog" => SyscallId::BenchmarkDumpLog,
+        #[cfg(feature = "SEL4_CONFIG_BENCHMARK")]
+        "BenchmarkLogSize" => SyscallId::BenchmarkLogSize,
+        _ => return None,
+    })
+}
+
+/* The part of an exported line after the prefix. */
+fn parse_entry(line: &str) -> Option<Entry> {
+    let mut f = Fields(line.split_whitespace());
+    let seq = some!(f.word().and_then(|w| w.parse().ok()));
+    let syscall = some!(f.word().and_then(syscall_named));
+    let dest = some!(f.hex());
+    let info = some!(f.info());
+    let mrs = some!(f.mrs());
+    let outcome = match some!(f.word()) {
+        "-" => None,
+        "done" => Some(Outcome::Done),
+        "value" => Some(Outcome::Value(some!(f.hex()))),
+        "reply" => {
+            let info = some!(f.info());
+            let badge = some!(f.hex());
+            Some(Outcome::Reply(info, badge, some!(f.mrs())))
+        }
+        _ => return None,
+    };
+    if f.word().is_some() {
+        return None;
+    }
+    Some(Entry { seq: seq, syscall: syscall, dest: dest, info: info, mrs: mrs, outcome: outcome })
+}
+
+/// Read the entries printed by `trace::export` or `trace::stream` out of
+/// `log`, ignoring every other line. Where a sequence number appears more
+/// than once the last line for it wins. Entries come back in sequence
+/// order.
+pub fn parse(log: &str) -> Result<Vec<Entry>, ParseError> {
+    let mut entries: Vec<Entry> = Vec::new();
+    for (i, line) in log.lines().enumerate() {
+        let rest = match line.find(EXPORT_PREFIX) {
+            Some(at) => &line[at + EXPORT_PREFIX.len()..],
+            None => continue,
+        };
+        let entry = try!(parse_entry(rest).ok_or(ParseError { line: i + 1 }));
+        match entries.iter().rposition(|e| e.seq == entry.seq) {
+            Some(at) => entries[at] = entry,
+            None => entries.push(entry),
+        }
+    }
+    entries.sort_by(|a, b| a.seq.cmp(&b.seq));
+    Ok(entries)
+}
+
+/// How a replay went wrong. `index` counts syscalls from 0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mismatch {
+    /// The capture has `expected` here, but the component made `actual`.
+    Different { index: usize, expected: Entry, actual: Entry },
+    /// The capture has ended, but the component made `actual`.
+    Extra { index: usize, actual: Entry },
+    /// The component returned, but the capture goes on with `expected`.
+    Missing { index: usize, expected: Entry },
+    /// The component panicked.
+    Panicked { index: usize },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Mismatch::Different { index, ref expected, ref actual } => {
+                write!(f, "syscall {}: expected\n{}\nbut the component made\n{}", index, expected, actual)
+            }
+            Mismatch::Extra { index, ref actual } => {
+                write!(f, "syscall {}: the capture has ended, but the component made\n{}", index, actual)
+            }
+            Mismatch::Missing { index, ref expected } => {
+                write!(f, "syscall {}: the component returned, but the capture goes on with\n{}",
+                       index, expected)
+            }
+            Mismatch::Panicked { index } => write!(f, "syscall {}: the component panicked", index),
+        }
+    }
+}
+
+struct Progress {
+    script: Vec<Entry>,
+    next: usize,
+    /* Set when a syscall stops the replay early: Ok if the capture ended
+     * inside that syscall, else the mismatch. */
+    stopped: Option<Result<(), Mismatch>>,
+}
+
+thread_local!(static REPLAY: RefCell<Option<Arc<Mutex<Progress>>>> = RefCell::new(None));
+
+/* Payload that unwinds the component's thread when a replay stops early.
+ * It is raised with resume_unwind, which skips the panic hook, so stopping
+ * prints no panic message; a panic of the component's own still does. */
+struct Stopped;
+
+/// Run `component` on a new host thread with its syscalls answered from
+/// `script`, checking that it makes the same syscalls in the same order.
+///
+/// If the capture ends inside a syscall that never returned, such as the
+/// final `seL4_Wait` of a server, the component is stopped there and the
+/// replay counts as a success. Returns the number of syscalls replayed.
+///
+/// The component is stopped by unwinding its thread, so it must not catch
+/// unwinds around its syscalls.
+pub fn run<F>(script: Vec<Entry>, component: F) -> Result<usize, Mismatch>
+    where F: FnOnce() + Send + 'static
+{
+    let progress = Arc::new(Mutex::new(Progress { script: script, next: 0, stopped: None }));
+    let shared = progress.clone();
+    let returned = thread::spawn(move || {
+        REPLAY.with(|r| *r.borrow_mut() = Some(shared));
+        component();
+    }).join().is_ok();
+
+    let mut p = progress.lock().unwrap();
+    let stopped = p.stopped.take();
+    match stopped {
+        Some(Ok(())) => Ok(p.next),
+        Some(Err(mismatch)) => Err(mismatch),
+        None if !returned => Err(Mismatch::Panicked { index: p.next }),
+        None if p.next < p.script.len() => {
+            Err(Mismatch::Missing { index: p.next, expected: p.script[p.next] })
+        }
+        None => Ok(p.next),
+    }
+}
+
+#[doc(hidden)]
+pub unsafe fn step(syscall: SyscallId, dest: seL4_CPtr, info: &seL4_MessageInfo,
+                   mr0: seL4_Word, mr1: seL4_Word) -> Option<Outcome> {
+    let progress = match REPLAY.with(|r| r.borrow().clone()) {
+        Some(progress) => progress,
+        None => return None,
+    };
+    let actual = Entry::sent(syscall, dest, info, mr0, mr1);
+    let stopped = {
+        let mut p = progress.lock().unwrap();
+        let index = p.next;
+        let expected = p.script.get(index).cloned();
+        match expected {
+            None => Err(Mismatch::Extra { index: index, actual: actual }),
+            Some(expected) if !expected.same_call(&actual) => {
+                Err(Mismatch::Different { index: index, expected: expected, actual: actual })
+            }
+            Some(expected) => {
+                p.next += 1;
+                match expected.outcome {
+                    Some(outcome) => return Some(outcome),
+                    None => Ok(()),
+                }
+            }
+        }
+    };
+    progress.lock().unwrap().stopped = Some(stopped);
+    panic::resume_unwind(Box::new(Stopped));
+}
+
+/* Deliver a replayed message to the caller: the MRs go to the IPC buffer,
+ * and the MessageInfo and badge are returned. */
+#[doc(hidden)]
+pub unsafe fn received(outcome: Outcome) -> (seL4_MessageInfo, seL4_Word) {
+    match outcome {
+        Outcome::Reply(info, badge, mrs) => {
+            for i in 0..cmp::min(info.length as usize, TRACE_MRS) {
+                seL4_SetMR(i as isize, mrs[i]);
+            }
+            (info.encode(), badge)
+        }
+        _ => (seL4_MessageInfo::new(0, 0, 0, 0), 0),
+    }
+}
+
+#[doc(hidden)]
+pub fn value(outcome: Outcome) -> seL4_Word {
+    match outcome {
+        Outcome::Value(value) => value,
+        _ => 0,
+    }
+}
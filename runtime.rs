@@ -0,0 +1,79 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+//! Root task startup.
+//!
+//! With the `runtime` feature, the crate links in a `_start` for the root
+//! task (see `arch/*_start.S`). It switches to a static stack and calls
+//! `__sel4_start_root` with the bootinfo pointer the kernel passed in, which
+//! records the bootinfo, sets up the IPC buffer and runs the function
+//! declared with `sel4_root_task!`. If that function returns, the initial
+//! thread suspends itself.
+
+use super::*;
+use thread;
+
+extern "Rust" {
+    fn __sel4_root_main(bootinfo: &'static seL4_BootInfo);
+}
+
+extern "C" {
+    /* Lives in libsel4_start.a. `sel4_root_task!` refers to it so the
+     * linker pulls it out of the archive. */
+    #[doc(hidden)]
+    pub fn _start();
+}
+
+#[doc(hidden)]
+#[no_mangle]
+pub unsafe extern "C" fn __sel4_start_root(bootinfo: *const seL4_BootInfo) -> ! {
+    init_boot_info(bootinfo);
+    thread::init_ipc_buffer((*bootinfo).ipcBuffer);
+
+    __sel4_root_main(get_boot_info());
+
+    seL4_TCB_Suspend(seL4_Cap::seL4_CapInitThreadTCB as seL4_TCB);
+    loop { }
+}
+
+/// Declare the entry point of the root task.
+///
+/// The function is called once the runtime has set up the stack, bootinfo
+/// and IPC buffer:
+///
+/// ```ignore
+/// sel4_root_task!(main);
+///
+/// fn main(bootinfo: &'static seL4_BootInfo) {
+///     ...
+/// }
+/// ```
+///
+/// This is a macro rather than an attribute on `main` because attributes
+/// can only be defined by compiler plugins, which are unstable and would
+/// have to be loaded by every root task.
+///
+/// The expansion also refers to the runtime's `_start`, so that it is
+/// linked in without the root task naming it.
+#[macro_export]
+macro_rules! sel4_root_task {
+    ($main:path) => {
+        #[doc(hidden)]
+        #[no_mangle]
+        pub fn __sel4_root_main(bootinfo: &'static $crate::seL4_BootInfo) {
+            let main: fn(&'static $crate::seL4_BootInfo) = $main;
+            main(bootinfo)
+        }
+
+        #[doc(hidden)]
+        #[no_mangle]
+        pub static __sel4_start: unsafe extern "C" fn() = $crate::runtime::_start;
+    }
+}
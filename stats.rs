@@ -0,0 +1,65 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+//! Summary statistics for cycle counts.
+
+use core::cmp;
+
+/// Summary of a set of samples.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub count: usize,
+    pub min: u32,
+    pub max: u32,
+    pub mean: u32,
+    pub median: u32,
+    pub p90: u32,
+    pub p99: u32,
+}
+
+impl Stats {
+    /// Summarise `samples`, sorting them in place.
+    ///
+    /// Returns `None` if there are no samples.
+    pub fn new(samples: &mut [u32]) -> Option<Stats> {
+        if samples.is_empty() {
+            return None;
+        }
+        sort(samples);
+
+        let sum = samples.iter().fold(0u64, |acc, &s| acc + s as u64);
+        Some(Stats {
+            count: samples.len(),
+            min: samples[0],
+            max: samples[samples.len() - 1],
+            mean: (sum / samples.len() as u64) as u32,
+            median: percentile(samples, 50),
+            p90: percentile(samples, 90),
+            p99: percentile(samples, 99),
+        })
+    }
+}
+
+/// The `p`th percentile of sorted, non-empty `samples` (nearest rank).
+pub fn percentile(samples: &[u32], p: usize) -> u32 {
+    let rank = (p * samples.len() + 99) / 100;
+    samples[cmp::max(rank, 1) - 1]
+}
+
+/* There is no sort in core; insertion sort is fine for benchmark runs. */
+fn sort(samples: &mut [u32]) {
+    for i in 1..samples.len() {
+        let mut j = i;
+        while j > 0 && samples[j - 1] > samples[j] {
+            samples.swap(j - 1, j);
+            j -= 1;
+        }
+    }
+}
@@ -0,0 +1,263 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+//! A test harness for root tasks.
+//!
+//! `sel4_tests!` declares test functions and a root task that runs them,
+//! reporting in TAP on the debug console for tools/qemu_test.py to read:
+//!
+//! ```ignore
+//! #![feature(no_std)]
+//! #![no_std]
+//! #![no_main]
+//!
+//! #[macro_use]
+//! extern crate sel4_sys;
+//!
+//! sel4_tests! {
+//!     fn copy_a_cap() {
+//!         ...
+//!     }
+//!
+//!     #[isolated]
+//!     fn touch_unmapped_memory() {
+//!         ...
+//!     }
+//! }
+//! ```
+//!
+//! The tests are a list inside one macro rather than functions marked with a
+//! `#[sel4_test]` attribute: a custom attribute needs a compiler plugin,
+//! which is unstable and can't be built for a root task's target, and the
+//! root task needs every test in one place to build its list from.
+//!
+//! Tests run one at a time, in the order declared, and pass by returning.
+//! Plain tests run on the initial thread, where a panic or fault ends the
+//! whole suite. A test marked `#[isolated]` runs on a thread of its own
+//! whose faults and panics are caught by the harness, so only that test
+//! fails.
+//!
+//! Isolated tests take their TCBs from the first untyped of at least
+//! `UNTYPED_BITS`, and use the last two slots of the bootinfo's empty
+//! region, which tests must leave alone. They share one stack and IPC
+//! buffer, have no TLS block, and run one priority below the root task,
+//! so they only get the CPU while the harness waits for them.
+
+use core::fmt;
+use core::ptr;
+use super::*;
+use fault::Fault;
+use thread::{self, IpcBuffer, ThreadBuilder, IPC_BUFFER_PAGES};
+
+/// A test declared with `sel4_tests!`.
+#[derive(Clone, Copy)]
+pub struct Test {
+    pub name: &'static str,
+    pub run: fn(),
+    /// Run on a thread of its own, catching its faults.
+    pub isolated: bool,
+}
+
+/// Smallest untyped isolated tests are taken from, as a power of two.
+/// Each one uses up a TCB.
+pub const UNTYPED_BITS: usize = 16;
+
+const PAGE_SIZE: usize = 1 << seL4_PageBits;
+const STACK_SIZE: usize = 16 * PAGE_SIZE;
+/* The initial thread runs at the highest priority; test threads run just
+ * below it, so they cannot start before the harness is waiting. */
+const TEST_PRIO: u8 = 254;
+
+/* Labels of the messages an isolated test's thread sends when it is done;
+ * fault messages have the nonzero fault labels. */
+const PASSED: seL4_Word = 0;
+const PANICKED: seL4_Word = 0xff;
+
+static mut CURRENT: Option<fn()> = None;
+static mut RUNNING: bool = false;
+static mut ENDPOINT: seL4_CPtr = 0;
+static mut TEST_TCB: seL4_TCB = 0;
+/* The test thread's IPC buffer, which tells its panics from others. */
+static mut TEST_IPC_BUFFER: seL4_Word = 0;
+
+static mut TEST_STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+static mut TEST_IPC_PAGES: [u8; IPC_BUFFER_PAGES] = [0; IPC_BUFFER_PAGES];
+
+#[derive(Clone, Copy)]
+struct Isolation {
+    untyped: seL4_Untyped,
+    endpoint: seL4_CPtr,
+    tcb: seL4_CPtr,
+    ipc_buffer: IpcBuffer,
+}
+
+enum Failure {
+    Fault(Fault),
+    Panicked,
+    Spawn(isize),
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Failure::Fault(ref fault) => write!(f, "fault: {:?}", fault),
+            Failure::Panicked => f.write_str("panicked"),
+            Failure::Spawn(err) => write!(f, "cannot start the test thread: error {}", err),
+        }
+    }
+}
+
+/// Run `tests` and print the results in TAP. This is the body of the root
+/// task that `sel4_tests!` declares.
+///
+/// The objects isolated tests need are set up before the first of them
+/// runs. If that fails, the suite bails out there.
+pub fn run(bootinfo: &'static seL4_BootInfo, tests: &[Test]) {
+    debug_println!("1..{}", tests.len());
+    let mut isolation = None;
+    let mut failed = 0;
+    for (i, test) in tests.iter().enumerate() {
+        let result = if test.isolated {
+            if isolation.is_none() {
+                match unsafe { setup(bootinfo) } {
+                    Ok(iso) => isolation = Some(iso),
+                    Err(what) => {
+                        debug_println!("Bail out! cannot isolate tests: {}", what);
+                        return;
+                    }
+                }
+            }
+            unsafe { run_isolated(&isolation.unwrap(), test.run) }
+        } else {
+            (test.run)();
+            Ok(())
+        };
+
+        match result {
+            Ok(()) => debug_println!("ok {} - {}", i + 1, test.name),
+            Err(failure) => {
+                failed += 1;
+                debug_println!("not ok {} - {}", i + 1, test.name);
+                debug_println!("# {}", failure);
+            }
+        }
+    }
+    debug_println!("# {} passed, {} failed", tests.len() - failed, failed);
+}
+
+unsafe fn setup(bootinfo: &seL4_BootInfo) -> Result<Isolation, &'static str> {
+    /* The endpoint goes in the second to last empty slot, and each test's
+     * TCB in the last. */
+    let endpoint = bootinfo.empty.end - 2;
+    let untyped = try!(thread::retype_from_bootinfo(bootinfo, UNTYPED_BITS,
+                                                    &[seL4_ObjectType::seL4_EndpointObject],
+                                                    endpoint));
+    let ipc_buffer = try!(IpcBuffer::in_image(bootinfo, &mut TEST_IPC_PAGES));
+
+    ENDPOINT = endpoint;
+    Ok(Isolation {
+        untyped: untyped,
+        endpoint: endpoint,
+        tcb: bootinfo.empty.end - 1,
+        ipc_buffer: ipc_buffer,
+    })
+}
+
+unsafe fn run_isolated(iso: &Isolation, test: fn()) -> Result<(), Failure> {
+    CURRENT = Some(test);
+    TEST_TCB = iso.tcb;
+    TEST_IPC_BUFFER = iso.ipc_buffer.vaddr;
+    let thread = ThreadBuilder::new(isolated_start, 0)
+                     .stack(&mut TEST_STACK)
+                     .ipc_buffer(iso.ipc_buffer)
+                     .priority(TEST_PRIO)
+                     .fault_endpoint(iso.endpoint)
+                     .name("test")
+                     .spawn(iso.untyped, iso.tcb);
+    let thread = match thread {
+        Ok(thread) => thread,
+        Err(err) => return Err(Failure::Spawn(err)),
+    };
+    RUNNING = true;
+
+    /* Decode before destroying the thread overwrites the message. */
+    let info = seL4_Wait(iso.endpoint, ptr::null_mut());
+    let result = match info.get_label() {
+        PASSED => Ok(()),
+        PANICKED => Err(Failure::Panicked),
+        _ => Err(Failure::Fault(Fault::new(info))),
+    };
+    RUNNING = false;
+    if let Err(err) = thread.destroy() {
+        /* The TCB slot stays taken, so later isolated tests fail to spawn. */
+        debug_println!("# cannot destroy the test thread: error {}", err);
+    }
+    result
+}
+
+fn isolated_start(_arg: seL4_Word) {
+    unsafe {
+        if let Some(test) = CURRENT {
+            test();
+        }
+        seL4_Send(ENDPOINT, seL4_MessageInfo::new(PASSED, 0, 0, 0));
+    }
+}
+
+/// Called by the panic handler. If the panic is on the thread of a running
+/// isolated test, report it to the harness and stop the thread. Otherwise
+/// return.
+#[doc(hidden)]
+pub fn panicked(msg: fmt::Arguments, file: &'static str, line: u32) {
+    unsafe {
+        if !RUNNING || seL4_GetIPCBuffer() as seL4_Word != TEST_IPC_BUFFER {
+            return;
+        }
+        debug_println!("# panicked at '{}', {}:{}", msg, file, line);
+        seL4_Send(ENDPOINT, seL4_MessageInfo::new(PANICKED, 0, 0, 0));
+        seL4_TCB_Suspend(TEST_TCB);
+    }
+}
+
+/// Declare the tests of a test root task.
+///
+/// Expands to the test functions and a `sel4_root_task!` entry point that
+/// hands them to `testing::run`. Each test is a `fn name() { ... }`,
+/// optionally preceded by `#[isolated]`; see the module documentation.
+#[macro_export]
+macro_rules! sel4_tests {
+    (@tests [$($done:tt)*]) => {
+        sel4_tests!(@emit $($done)*);
+    };
+    (@tests [$($done:tt)*] #[isolated] fn $name:ident() $body:block $($rest:tt)*) => {
+        sel4_tests!(@tests [$($done)* ($name true $body)] $($rest)*);
+    };
+    (@tests [$($done:tt)*] fn $name:ident() $body:block $($rest:tt)*) => {
+        sel4_tests!(@tests [$($done)* ($name false $body)] $($rest)*);
+    };
+    (@emit $(($name:ident $isolated:tt $body:block))*) => {
+        $(fn $name() $body)*
+
+        sel4_root_task!(__sel4_test_main);
+
+        fn __sel4_test_main(bootinfo: &'static $crate::seL4_BootInfo) {
+            $crate::testing::run(bootinfo, &[
+                $($crate::testing::Test {
+                    name: stringify!($name),
+                    run: $name,
+                    isolated: $isolated,
+                },)*
+            ]);
+        }
+    };
+    ($($tests:tt)*) => {
+        sel4_tests!(@tests [] $($tests)*);
+    };
+}
@@ -0,0 +1,29 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+//! Check the hand-written syscall stubs against the kernel ABI tables in
+//! tools/syscall_abi_check.py. This only checks the asm operand lists as
+//! written, not what the stubs do; see the tool's header.
+
+use std::process::Command;
+
+fn check(arch: &str, file: &str) {
+    let output = Command::new("/usr/bin/env")
+        .arg("python")
+        .args(&["tools/syscall_abi_check.py", "-a", arch, file])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn x86_stubs() {
+    check("x86", "arch/x86.rs");
+}
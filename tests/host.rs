@@ -0,0 +1,46 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+//! The data definitions, used the way host tools use them. Run with
+//! `cargo test --features host`.
+
+#![cfg(feature = "host")]
+
+extern crate sel4_sys;
+
+use sel4_sys::*;
+use sel4_sys::benchmark::{self, LogEntry};
+
+#[test]
+fn message_info_fields() {
+    let info = seL4_MessageInfo::new(InvocationLabel::UntypedRetype as seL4_Word, 1, 2, 3);
+    assert_eq!(info.get_label(), InvocationLabel::UntypedRetype as seL4_Word);
+    assert_eq!(info.get_capsUnwrapped(), 1);
+    assert_eq!(info.get_extraCaps(), 2);
+    assert_eq!(info.get_length(), 3);
+}
+
+#[test]
+fn labels_start_after_invalid() {
+    assert_eq!(InvocationLabel::InvalidInvocation as u32, 0);
+    assert!(InvocationLabel::UntypedRetype as u32 > 0);
+}
+
+#[test]
+fn decode_benchmark_log() {
+    let log = [
+        LogEntry { event: 1, timestamp: 0xffff_fff0 },
+        LogEntry { event: 2, timestamp: 0x10 },
+        LogEntry { event: 3, timestamp: 0x30 },
+    ];
+    let mut out = [0; 2];
+    assert_eq!(benchmark::intervals(&log, &mut out), 2);
+    assert_eq!(out, [0x20, 0x20]);
+}
@@ -0,0 +1,76 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+//! Check `seL4_IPCBuffer` against the layout the kernel uses.
+//!
+//! On the host this checks the ia32 definitions (`--features host` or
+//! `--features mock`); built for an seL4 target it checks that target's.
+
+#![cfg(any(feature = "host", feature = "mock",
+           all(target_arch = "x86", target_pointer_width = "32"),
+           all(target_arch = "arm", target_pointer_width = "32")))]
+
+extern crate sel4_sys;
+
+use sel4_sys::*;
+use std::mem;
+
+macro_rules! offset_of {
+    ($field:ident) => {
+        unsafe { &(*(0 as *const seL4_IPCBuffer)).$field as *const _ as usize }
+    }
+}
+
+/* Byte offsets of the fields in the kernel's seL4_IPCBuffer. Both 32-bit
+ * architectures share this layout. */
+const TAG: usize = 0;
+const MSG: usize = 4;
+const USER_DATA: usize = 484;
+const CAPS_OR_BADGES: usize = 488;
+const RECEIVE_CNODE: usize = 500;
+const RECEIVE_INDEX: usize = 504;
+const RECEIVE_DEPTH: usize = 508;
+const SIZE: usize = 512;
+
+#[test]
+fn field_offsets() {
+    assert_eq!(offset_of!(tag), TAG);
+    assert_eq!(offset_of!(msg), MSG);
+    assert_eq!(offset_of!(userData), USER_DATA);
+    assert_eq!(offset_of!(caps_or_badges), CAPS_OR_BADGES);
+    assert_eq!(offset_of!(receiveCNode), RECEIVE_CNODE);
+    assert_eq!(offset_of!(receiveIndex), RECEIVE_INDEX);
+    assert_eq!(offset_of!(receiveDepth), RECEIVE_DEPTH);
+}
+
+#[test]
+fn size() {
+    assert_eq!(mem::size_of::<seL4_IPCBuffer>(), SIZE);
+    assert_eq!(SIZE, 1 << seL4_IPCBufferSizeBits);
+}
+
+#[test]
+fn array_lengths() {
+    assert_eq!(USER_DATA - MSG, seL4_MsgMaxLength * mem::size_of::<seL4_Word>());
+    assert_eq!(RECEIVE_CNODE - CAPS_OR_BADGES, seL4_MsgMaxExtraCaps * mem::size_of::<seL4_Word>());
+}
+
+/* The x86 accessors address the buffer with these constants. */
+#[cfg(any(feature = "host", feature = "mock", target_arch = "x86"))]
+#[test]
+fn accessor_offsets() {
+    assert_eq!(IPC_BUFFER_TAG, offset_of!(tag));
+    assert_eq!(IPC_BUFFER_MSG, offset_of!(msg));
+    assert_eq!(IPC_BUFFER_USER_DATA, offset_of!(userData));
+    assert_eq!(IPC_BUFFER_CAPS_OR_BADGES, offset_of!(caps_or_badges));
+    assert_eq!(IPC_BUFFER_RECEIVE_CNODE, offset_of!(receiveCNode));
+    assert_eq!(IPC_BUFFER_RECEIVE_INDEX, offset_of!(receiveIndex));
+    assert_eq!(IPC_BUFFER_RECEIVE_DEPTH, offset_of!(receiveDepth));
+}
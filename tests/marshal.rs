@@ -0,0 +1,274 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+//! Golden encodings of every invocation in sel4.xml and the x86
+//! sel4arch.xml, checked against what the generated stubs put in the IPC
+//! buffer. Run with `cargo test --features mock`.
+//!
+//! The expected words are the kernel's view: word `n` is what its decoder
+//! reads as `getSyscallArg(n)`, and cap `n` is `extraCaps[n]`. A generator
+//! change that moves, packs or drops an argument fails here rather than on
+//! hardware.
+
+#![cfg(all(feature = "mock", not(feature = "host")))]
+
+extern crate sel4_sys;
+
+use sel4_sys::*;
+use sel4_sys::mock::{self, Invocation};
+use std::fs::File;
+use std::io::Read;
+use std::mem;
+
+const SERVICE: seL4_CPtr = 0x100;
+const CAP0: seL4_CPtr = 0x200;
+const CAP1: seL4_CPtr = 0x201;
+const CAP2: seL4_CPtr = 0x202;
+
+const XML: [&'static str; 2] = [
+    "seL4/libsel4/include/interfaces/sel4.xml",
+    "seL4/libsel4/arch_include/x86/interfaces/sel4arch.xml",
+];
+
+fn data(word: seL4_Word) -> seL4_CapData {
+    unsafe { mem::transmute([word]) }
+}
+
+fn context() -> seL4_UserContext {
+    seL4_UserContext {
+        eip: 0xc0, esp: 0xc1, eflags: 0xc2, eax: 0xc3, ebx: 0xc4, ecx: 0xc5, edx: 0xc6,
+        esi: 0xc7, edi: 0xc8, ebp: 0xc9, tls_base: 0xca, fs: 0xcb, gs: 0xcc,
+    }
+}
+
+/* Run `f` against a fresh mock kernel, answering its Call with `label` and
+ * `mrs`, and return the one message it sent. */
+fn sent<F: FnOnce()>(label: seL4_Word, mrs: &[seL4_Word], f: F) -> Invocation {
+    mock::boot();
+    mock::record();
+    mock::canned_reply(label, mrs);
+    f();
+    let mut sent = mock::recorded();
+    assert_eq!(sent.len(), 1);
+    sent.pop().unwrap()
+}
+
+macro_rules! golden {
+    ($($name:ident: $call:expr => $label:ident [$($mr:expr),*] [$($cap:expr),*];)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let invocation = sent(0, &[], || unsafe { let _ = $call; });
+                assert_eq!(invocation, Invocation {
+                    dest: SERVICE,
+                    label: InvocationLabel::$label as seL4_Word,
+                    mrs: vec![$($mr),*],
+                    caps: vec![$($cap),*],
+                });
+            }
+        )*
+
+        const LABELS: &'static [&'static str] = &[$(stringify!($label)),*];
+    }
+}
+
+golden! {
+    untyped_retype:
+        seL4_Untyped_Retype(SERVICE, 4, 5, CAP0, 6, 7, 8, 9)
+        => UntypedRetype [4, 5, 6, 7, 8, 9] [CAP0];
+
+    tcb_read_registers:
+        seL4_TCB_ReadRegisters(SERVICE, 1, 0x5a, 13, &mut context())
+        => TCBReadRegisters [0x5a01, 13] [];
+    tcb_write_registers:
+        seL4_TCB_WriteRegisters(SERVICE, 1, 0x5a, 13, &mut context())
+        => TCBWriteRegisters [0x5a01, 13, 0xc0, 0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7,
+                              0xc8, 0xc9, 0xca, 0xcb, 0xcc] [];
+    tcb_copy_registers:
+        seL4_TCB_CopyRegisters(SERVICE, CAP0, 1, 1, 0, 1, 0x5a)
+        => TCBCopyRegisters [0x5a0b] [CAP0];
+    tcb_configure:
+        seL4_TCB_Configure(SERVICE, 0x11, 0x7f, CAP0, data(0xd0), CAP1, data(0xd1), 0x4000, CAP2)
+        => TCBConfigure [0x11, 0x7f, 0xd0, 0xd1, 0x4000] [CAP0, CAP1, CAP2];
+    tcb_set_priority:
+        seL4_TCB_SetPriority(SERVICE, 0x7f)
+        => TCBSetPriority [0x7f] [];
+    tcb_set_ipc_buffer:
+        seL4_TCB_SetIPCBuffer(SERVICE, 0x4000, CAP0)
+        => TCBSetIPCBuffer [0x4000] [CAP0];
+    tcb_set_space:
+        seL4_TCB_SetSpace(SERVICE, 0x11, CAP0, data(0xd0), CAP1, data(0xd1))
+        => TCBSetSpace [0x11, 0xd0, 0xd1] [CAP0, CAP1];
+    tcb_suspend:
+        seL4_TCB_Suspend(SERVICE)
+        => TCBSuspend [] [];
+    tcb_resume:
+        seL4_TCB_Resume(SERVICE)
+        => TCBResume [] [];
+
+    cnode_revoke:
+        seL4_CNode_Revoke(SERVICE, 0x21, 32)
+        => CNodeRevoke [0x21, 32] [];
+    cnode_delete:
+        seL4_CNode_Delete(SERVICE, 0x21, 32)
+        => CNodeDelete [0x21, 32] [];
+    cnode_recycle:
+        seL4_CNode_Recycle(SERVICE, 0x21, 32)
+        => CNodeRecycle [0x21, 32] [];
+    cnode_copy:
+        seL4_CNode_Copy(SERVICE, 0x21, 32, CAP0, 0x22, 16, seL4_CapRights::CanRead)
+        => CNodeCopy [0x21, 32, 0x22, 16, 2] [CAP0];
+    cnode_mint:
+        seL4_CNode_Mint(SERVICE, 0x21, 32, CAP0, 0x22, 16, seL4_CapRights::CanRead, data(0xd0))
+        => CNodeMint [0x21, 32, 0x22, 16, 2, 0xd0] [CAP0];
+    cnode_move:
+        seL4_CNode_Move(SERVICE, 0x21, 32, CAP0, 0x22, 16)
+        => CNodeMove [0x21, 32, 0x22, 16] [CAP0];
+    cnode_mutate:
+        seL4_CNode_Mutate(SERVICE, 0x21, 32, CAP0, 0x22, 16, data(0xd0))
+        => CNodeMutate [0x21, 32, 0x22, 16, 0xd0] [CAP0];
+    cnode_rotate:
+        seL4_CNode_Rotate(SERVICE, 0x21, 32, data(0xd0), CAP0, 0x22, 16, data(0xd1), CAP1, 0x23, 8)
+        => CNodeRotate [0x21, 32, 0xd0, 0x22, 16, 0xd1, 0x23, 8] [CAP0, CAP1];
+    cnode_save_caller:
+        seL4_CNode_SaveCaller(SERVICE, 0x21, 32)
+        => CNodeSaveCaller [0x21, 32] [];
+
+    irq_control_get:
+        seL4_IRQControl_Get(SERVICE, 9, CAP0, 0x21, 32)
+        => IRQIssueIRQHandler [9, 0x21, 32] [CAP0];
+    irq_handler_ack:
+        seL4_IRQHandler_Ack(SERVICE)
+        => IRQAckIRQ [] [];
+    irq_handler_set_endpoint:
+        seL4_IRQHandler_SetEndpoint(SERVICE, CAP0)
+        => IRQSetIRQHandler [] [CAP0];
+    irq_handler_clear:
+        seL4_IRQHandler_Clear(SERVICE)
+        => IRQClearIRQHandler [] [];
+
+    domain_set_set:
+        seL4_DomainSet_Set(SERVICE, 3, CAP0)
+        => DomainSetSet [3] [CAP0];
+
+    ia32_page_table_map:
+        seL4_IA32_PageTable_Map(SERVICE, CAP0, 0x4000_0000, seL4_IA32_VMAttributes::WriteThrough)
+        => IA32PageTableMap [0x4000_0000, 1] [CAP0];
+    ia32_page_table_unmap:
+        seL4_IA32_PageTable_Unmap(SERVICE)
+        => IA32PageTableUnmap [] [];
+    ia32_io_page_table_map:
+        seL4_IA32_IOPageTable_Map(SERVICE, CAP0, 0x8000)
+        => IA32IOPageTableMap [0x8000] [CAP0];
+    ia32_page_map:
+        seL4_IA32_Page_Map(SERVICE, CAP0, 0x4000_0000, seL4_CapRights::CanRead,
+                           seL4_IA32_VMAttributes::CacheDisabled)
+        => IA32PageMap [0x4000_0000, 2, 2] [CAP0];
+    ia32_page_remap:
+        seL4_IA32_Page_Remap(SERVICE, CAP0, seL4_CapRights::CanRead,
+                             seL4_IA32_VMAttributes::CacheDisabled)
+        => IA32PageRemap [2, 2] [CAP0];
+    ia32_page_unmap:
+        seL4_IA32_Page_Unmap(SERVICE)
+        => IA32PageUnmap [] [];
+    ia32_page_map_io:
+        seL4_IA32_Page_MapIO(SERVICE, CAP0, seL4_CapRights::CanRead, 0x8000)
+        => IA32PageMapIO [2, 0x8000] [CAP0];
+    ia32_page_get_address:
+        seL4_IA32_Page_GetAddress(SERVICE)
+        => IA32PageGetAddress [] [];
+    ia32_asid_control_make_pool:
+        seL4_IA32_ASIDControl_MakePool(SERVICE, CAP0, CAP1, 0x21, 32)
+        => IA32ASIDControlMakePool [0x21, 32] [CAP0, CAP1];
+    ia32_asid_pool_assign:
+        seL4_IA32_ASIDPool_Assign(SERVICE, CAP0)
+        => IA32ASIDPoolAssign [] [CAP0];
+    ia32_io_port_in8:
+        seL4_IA32_IOPort_In8(SERVICE, 0x3f8)
+        => IA32IOPortIn8 [0x3f8] [];
+    ia32_io_port_in16:
+        seL4_IA32_IOPort_In16(SERVICE, 0x3f8)
+        => IA32IOPortIn16 [0x3f8] [];
+    ia32_io_port_in32:
+        seL4_IA32_IOPort_In32(SERVICE, 0x3f8)
+        => IA32IOPortIn32 [0x3f8] [];
+    ia32_io_port_out8:
+        seL4_IA32_IOPort_Out8(SERVICE, 0x3f8, 0x41)
+        => IA32IOPortOut8 [0x3f8, 0x41] [];
+    ia32_io_port_out16:
+        seL4_IA32_IOPort_Out16(SERVICE, 0x3f8, 0x4142)
+        => IA32IOPortOut16 [0x3f8, 0x4142] [];
+    ia32_io_port_out32:
+        seL4_IA32_IOPort_Out32(SERVICE, 0x3f8, 0x4142_4344)
+        => IA32IOPortOut32 [0x3f8, 0x4142_4344] [];
+}
+
+#[test]
+fn covers_every_invocation() {
+    for file in XML.iter() {
+        let mut xml = String::new();
+        File::open(file).unwrap().read_to_string(&mut xml).unwrap();
+        for method in xml.split("<method").skip(1) {
+            let id = method.split("id=\"").nth(1).and_then(|s| s.split('"').next()).unwrap();
+            assert!(LABELS.contains(&id), "no golden encoding for {} from {}", id, file);
+        }
+    }
+}
+
+#[test]
+fn unpack_registers() {
+    let words = [0xc0, 0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xcb, 0xcc];
+    let mut regs = seL4_UserContext::default();
+    let mut err = -1;
+    sent(0, &words, || unsafe {
+        err = seL4_TCB_ReadRegisters(SERVICE, 0, 0, 13, &mut regs);
+    });
+    assert_eq!(err, 0);
+    assert_eq!(regs, context());
+}
+
+#[test]
+fn unpack_page_address() {
+    let mut result = None;
+    sent(0, &[0x1234_5000], || unsafe {
+        result = Some(seL4_IA32_Page_GetAddress(SERVICE));
+    });
+    let result = result.unwrap();
+    assert_eq!(result.error, 0);
+    assert_eq!(result.paddr, 0x1234_5000);
+}
+
+#[test]
+fn unpack_io_port_reads() {
+    let mut results = [0; 3];
+    sent(0, &[0xaabb_ccdd], || unsafe {
+        results[0] = seL4_IA32_IOPort_In8(SERVICE, 0x3f8).result as seL4_Word;
+    });
+    sent(0, &[0xaabb_ccdd], || unsafe {
+        results[1] = seL4_IA32_IOPort_In16(SERVICE, 0x3f8).result as seL4_Word;
+    });
+    sent(0, &[0xaabb_ccdd], || unsafe {
+        results[2] = seL4_IA32_IOPort_In32(SERVICE, 0x3f8).result as seL4_Word;
+    });
+    assert_eq!(results, [0xdd, 0xccdd, 0xaabb_ccdd]);
+}
+
+#[test]
+fn errors_come_from_the_reply_label() {
+    let err = seL4_Error::seL4_InvalidArgument as seL4_Word;
+    let mut results = (0, 0);
+    sent(err, &[], || unsafe {
+        results.0 = seL4_TCB_Suspend(SERVICE);
+    });
+    sent(err, &[], || unsafe {
+        results.1 = seL4_IA32_Page_GetAddress(SERVICE).error;
+    });
+    assert_eq!(results, (err as isize, err as isize));
+}
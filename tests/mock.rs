@@ -0,0 +1,169 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+//! Exercise the generated stubs against the mock kernel. Run with
+//! `cargo test --features mock`.
+
+#![cfg(all(feature = "mock", not(feature = "host")))]
+
+extern crate sel4_sys;
+
+use sel4_sys::*;
+use sel4_sys::thread::{self, IpcBuffer};
+use std::mem;
+use std::ptr;
+
+const ROOT: seL4_CNode = seL4_Cap::seL4_CapInitThreadCNode as seL4_CNode;
+
+fn retype(bootinfo: &seL4_BootInfo, type_: seL4_ObjectType, slot: seL4_CPtr) {
+    let err = unsafe {
+        seL4_Untyped_Retype(bootinfo.untyped.start, type_ as seL4_Word, 0, ROOT, 0, 0, slot as isize, 1)
+    };
+    assert_eq!(err, 0);
+}
+
+fn configure(tcb: seL4_TCB) {
+    let err = unsafe {
+        seL4_TCB_Configure(tcb, 0, 255, ROOT, mem::zeroed(),
+                           seL4_Cap::seL4_CapInitThreadVSpace as seL4_CPtr, mem::zeroed(), 0, 0)
+    };
+    assert_eq!(err, 0);
+}
+
+#[test]
+fn call_and_reply() {
+    let bootinfo = mock::boot();
+    let ep = bootinfo.empty.start;
+    let tcb = ep + 1;
+    retype(bootinfo, seL4_ObjectType::seL4_EndpointObject, ep);
+    retype(bootinfo, seL4_ObjectType::seL4_TCBObject, tcb);
+    configure(tcb);
+
+    let server = mock::spawn(tcb, move || unsafe {
+        let info = seL4_Wait(ep, ptr::null_mut());
+        seL4_SetMR(0, seL4_GetMR(0) + seL4_GetMR(1));
+        seL4_Reply(seL4_MessageInfo::new(info.get_label() + 1, 0, 0, 1));
+    });
+    assert_eq!(unsafe { seL4_TCB_Resume(tcb) }, 0);
+
+    unsafe {
+        seL4_SetMR(0, 2);
+        seL4_SetMR(1, 3);
+        let reply = seL4_Call(ep, seL4_MessageInfo::new(7, 0, 0, 2));
+        assert_eq!(reply.get_label(), 8);
+        assert_eq!(reply.get_length(), 1);
+        assert_eq!(seL4_GetMR(0), 5);
+    }
+    server.join().unwrap();
+}
+
+#[test]
+fn unbadged_caps_are_unwrapped() {
+    let bootinfo = mock::boot();
+    let ep = bootinfo.empty.start;
+    let tcb = ep + 1;
+    retype(bootinfo, seL4_ObjectType::seL4_EndpointObject, ep);
+    retype(bootinfo, seL4_ObjectType::seL4_TCBObject, tcb);
+    configure(tcb);
+
+    let server = mock::spawn(tcb, move || unsafe {
+        let info = seL4_Wait(ep, ptr::null_mut());
+        assert_eq!(info.get_capsUnwrapped(), 1);
+        assert_eq!(info.get_extraCaps(), 1);
+        assert_eq!(seL4_GetBadge(0).words[0], 0);
+        seL4_Reply(seL4_MessageInfo::new(0, 0, 0, 0));
+    });
+    assert_eq!(unsafe { seL4_TCB_Resume(tcb) }, 0);
+
+    unsafe {
+        seL4_SetCap(0, ep);
+        seL4_Call(ep, seL4_MessageInfo::new(0, 0, 1, 0));
+    }
+    server.join().unwrap();
+}
+
+#[test]
+#[should_panic(expected = "holds no cap")]
+fn send_to_an_empty_slot_faults() {
+    let bootinfo = mock::boot();
+    unsafe { seL4_Send(bootinfo.empty.start, seL4_MessageInfo::new(0, 0, 0, 0)) };
+}
+
+#[test]
+fn notifications_accumulate() {
+    let bootinfo = mock::boot();
+    let aep = bootinfo.empty.start;
+    retype(bootinfo, seL4_ObjectType::seL4_AsyncEndpointObject, aep);
+
+    unsafe {
+        seL4_Notify(aep, 1);
+        seL4_Notify(aep, 4);
+        seL4_Wait(aep, ptr::null_mut());
+        assert_eq!(seL4_GetMR(0), 5);
+    }
+}
+
+#[test]
+fn retype_into_occupied_slot_fails() {
+    let bootinfo = mock::boot();
+    let slot = bootinfo.empty.start;
+    retype(bootinfo, seL4_ObjectType::seL4_EndpointObject, slot);
+
+    let err = unsafe {
+        seL4_Untyped_Retype(bootinfo.untyped.start, seL4_ObjectType::seL4_EndpointObject as seL4_Word,
+                            0, ROOT, 0, 0, slot as isize, 1)
+    };
+    assert_eq!(err, seL4_Error::seL4_DeleteFirst as isize);
+}
+
+#[test]
+fn registers_round_trip() {
+    let bootinfo = mock::boot();
+    let tcb = bootinfo.empty.start;
+    retype(bootinfo, seL4_ObjectType::seL4_TCBObject, tcb);
+
+    let mut regs = seL4_UserContext::new_thread(0x1000, 0x2000);
+    regs.set_arg(0, 42);
+    thread::write_all_registers(tcb, false, &regs).unwrap();
+    assert_eq!(thread::read_all_registers(tcb, false).unwrap(), regs);
+
+    let info = mock::thread_info(tcb).unwrap();
+    assert_eq!(info.regs, regs);
+    assert!(info.suspended);
+}
+
+#[test]
+fn ipc_buffer_needs_page_table() {
+    let bootinfo = mock::boot();
+    let slot = bootinfo.empty.start;
+    let vspace = seL4_Cap::seL4_CapInitThreadVSpace as seL4_IA32_PageDirectory;
+
+    let buffer = IpcBuffer::allocate(bootinfo.untyped.start, slot, Some(slot + 1), vspace,
+                                     0x4000_0000).unwrap();
+    assert_eq!(buffer, IpcBuffer { vaddr: 0x4000_0000, frame: slot });
+
+    let err = IpcBuffer::allocate(bootinfo.untyped.start, slot + 2, None, vspace,
+                                  0x8000_0000);
+    assert_eq!(err, Err(seL4_Error::seL4_FailedLookup as isize));
+    /* The frame was deleted again, leaving the slot free. */
+    retype(bootinfo, seL4_ObjectType::seL4_EndpointObject, slot + 2);
+}
+
+#[cfg(feature = "SEL4_DEBUG")]
+#[test]
+fn long_names_are_cut_between_characters() {
+    mock::boot();
+    let tcb = seL4_Cap::seL4_CapInitThreadTCB as seL4_TCB;
+    let name: String = std::iter::repeat("\u{e9}").take(seL4_MaxThreadNameLength).collect();
+    debug::name_root_thread(&name);
+    let named = mock::thread_info(tcb).unwrap().name;
+    assert_eq!(named.len(), seL4_MaxThreadNameLength - seL4_MaxThreadNameLength % 2);
+    assert!(name.starts_with(&*named));
+}
@@ -0,0 +1,35 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+//! Boot the root tasks in tests/root under QEMU with tools/qemu_test.py.
+//! Needs QEMU and a seL4 kernel image in SEL4_KERNEL, so it only runs when
+//! asked for, with `cargo test -- --ignored`.
+
+use std::env;
+use std::fs;
+use std::process::Command;
+
+#[test]
+#[ignore]
+fn x86_root_tasks() {
+    assert!(env::var("SEL4_KERNEL").is_ok(), "SEL4_KERNEL must name a seL4 kernel image");
+    let mut tests: Vec<String> = fs::read_dir("tests/root").unwrap()
+        .map(|e| e.unwrap().path().to_string_lossy().into_owned())
+        .filter(|p| p.ends_with(".rs"))
+        .collect();
+    tests.sort();
+    let status = Command::new("/usr/bin/env")
+        .arg("python")
+        .args(&["tools/qemu_test.py", "-a", "x86"])
+        .args(&tests)
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
@@ -0,0 +1,127 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+//! Replaying captured traces against the mock kernel. Run with
+//! `cargo test --features mock`, adding `trace` for the round trip.
+
+#![cfg(all(feature = "mock", not(feature = "host")))]
+
+extern crate sel4_sys;
+
+use sel4_sys::*;
+use sel4_sys::replay::{self, Mismatch, ParseError};
+
+const CNODE: seL4_CPtr = seL4_Cap::seL4_CapInitThreadCNode as seL4_CPtr;
+const PAGE: seL4_CPtr = 0x30;
+const SERVER: seL4_CPtr = 0x40;
+
+/* Looks up a frame's address and passes it on to a server. */
+fn component(slot: seL4_Word) {
+    unsafe {
+        let page = seL4_IA32_Page_GetAddress(PAGE);
+        assert_eq!(page.error, 0);
+        seL4_SetMR(0, page.paddr + 1);
+        let reply = seL4_Call(SERVER, seL4_MessageInfo::new(9, 0, 0, 1));
+        assert_eq!(reply.get_label(), 0);
+        seL4_CNode_Delete(CNODE, slot, 32);
+    }
+}
+
+/* What `component(0x21)` prints on real hardware, between other output. */
+fn capture() -> String {
+    format!("booting\n\
+             sel4-trace 1 Call 30 {:x} 0 0 0 0 0 0 0 -\n\
+             sel4-trace 1 Call 30 {:x} 0 0 0 0 0 0 0 reply 0 0 0 1 0 12345000 0 0 0\n\
+             component: sending the address\n\
+             sel4-trace 2 Call 40 9 0 0 1 12345001 0 0 0 reply 0 0 0 0 0 0 0 0 0\n\
+             sel4-trace 3 Call {:x} {:x} 0 0 2 21 20 0 0 reply 0 0 0 0 0 0 0 0 0\n",
+            InvocationLabel::IA32PageGetAddress as seL4_Word,
+            InvocationLabel::IA32PageGetAddress as seL4_Word,
+            CNODE,
+            InvocationLabel::CNodeDelete as seL4_Word)
+}
+
+#[test]
+fn parse_keeps_the_last_line_of_each_entry() {
+    let entries = replay::parse(&capture()).unwrap();
+    assert_eq!(entries.iter().map(|e| e.seq).collect::<Vec<_>>(), [1, 2, 3]);
+    assert!(entries.iter().all(|e| e.outcome.is_some()));
+    for entry in &entries {
+        let line = format!("{}", entry.export());
+        assert_eq!(replay::parse(&line).unwrap(), [*entry]);
+    }
+}
+
+#[test]
+fn parse_reports_bad_lines() {
+    assert_eq!(replay::parse("ok\nsel4-trace 1 Call 30 zz\n"), Err(ParseError { line: 2 }));
+}
+
+#[test]
+fn replay_matches_capture() {
+    let script = replay::parse(&capture()).unwrap();
+    assert_eq!(replay::run(script, || component(0x21)), Ok(3));
+}
+
+#[test]
+fn replay_reports_a_different_message() {
+    let script = replay::parse(&capture()).unwrap();
+    match replay::run(script, || component(0x22)) {
+        Err(Mismatch::Different { index: 2, expected, actual }) => {
+            assert_eq!(expected.mrs[0], 0x21);
+            assert_eq!(actual.mrs[0], 0x22);
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn replay_reports_a_short_capture() {
+    let mut script = replay::parse(&capture()).unwrap();
+    script.pop();
+    match replay::run(script, || component(0x21)) {
+        Err(Mismatch::Extra { index: 2, .. }) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn replay_stops_where_the_capture_did() {
+    let mut log = capture();
+    log.push_str("sel4-trace 4 Wait 50 0 0 0 0 0 0 0 0 -\n");
+    let script = replay::parse(&log).unwrap();
+    let served = replay::run(script, || {
+        component(0x21);
+        loop {
+            unsafe { seL4_Wait(0x50, std::ptr::null_mut()) };
+        }
+    });
+    assert_eq!(served, Ok(4));
+}
+
+#[cfg(feature = "trace")]
+#[test]
+fn trace_round_trips_through_replay() {
+    use sel4_sys::trace;
+
+    mock::boot();
+    trace::clear();
+    mock::canned_reply(0, &[0x1234_5000]);
+    mock::canned_reply(0, &[]);
+    mock::canned_reply(0, &[]);
+    component(0x21);
+
+    let log: String = trace::entries().iter()
+        .filter_map(|e| e.as_ref())
+        .map(|e| format!("{}\n", e.export()))
+        .collect();
+    let script = replay::parse(&log).unwrap();
+    assert_eq!(replay::run(script, || component(0x21)), Ok(3));
+}
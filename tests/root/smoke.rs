@@ -0,0 +1,52 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+//! A root task that boots and checks the basics: the bootinfo, an
+//! invocation, the debug syscalls and the test harness itself. Run with
+//! tools/qemu_test.py.
+
+#![feature(no_std)]
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate sel4_sys;
+
+use sel4_sys::*;
+use sel4_sys::debug::identify_cap;
+
+/* The initial CNode resolves a whole word of cptr. */
+const DEPTH: u8 = 32;
+
+sel4_tests! {
+    fn bootinfo_lists_untyped_memory() {
+        let bootinfo = get_boot_info();
+        assert!(bootinfo.untyped.end > bootinfo.untyped.start);
+    }
+
+    fn copy_the_tcb_cap() {
+        let cnode = seL4_Cap::seL4_CapInitThreadCNode as seL4_CNode;
+        let slot = get_boot_info().empty.start;
+        let err = unsafe {
+            seL4_CNode_Copy(cnode, slot, DEPTH, cnode, seL4_Cap::seL4_CapInitThreadTCB as seL4_Word,
+                            DEPTH, seL4_CapRights::AllRights)
+        };
+        assert_eq!(err, 0);
+        assert!(identify_cap(slot) == seL4_CapType::ThreadCap);
+    }
+
+    #[isolated]
+    fn isolated_thread_has_an_ipc_buffer() {
+        unsafe {
+            seL4_SetMR(0, 0x5e14);
+            assert_eq!(seL4_GetMR(0), 0x5e14);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+//! The syscall trace, recorded against the mock kernel. Run with
+//! `cargo test --features "mock trace"`.
+
+#![cfg(all(feature = "mock", feature = "trace", not(feature = "host")))]
+
+extern crate sel4_sys;
+
+use sel4_sys::*;
+use sel4_sys::trace::{self, Entry, Info, Outcome, TRACE_ENTRIES};
+
+fn recorded() -> Vec<Entry> {
+    trace::entries().iter().filter_map(|e| *e).collect()
+}
+
+#[test]
+fn invocation_is_recorded() {
+    mock::boot();
+    trace::clear();
+    mock::canned_reply(seL4_Error::seL4_InvalidArgument as seL4_Word, &[]);
+    unsafe {
+        seL4_CNode_Delete(0x100, 0x21, 32);
+    }
+
+    let entries = recorded();
+    assert_eq!(entries.len(), 1);
+    let entry = entries[0];
+    assert_eq!(entry.syscall, SyscallId::Call);
+    assert_eq!(entry.dest, 0x100);
+    assert_eq!(entry.info, Info {
+        label: InvocationLabel::CNodeDelete as seL4_Word,
+        caps_unwrapped: 0,
+        extra_caps: 0,
+        length: 2,
+    });
+    assert_eq!(entry.mrs, [0x21, 32, 0, 0]);
+    match entry.outcome {
+        Some(Outcome::Reply(info, 0, _)) => {
+            assert_eq!(info.label, seL4_Error::seL4_InvalidArgument as seL4_Word)
+        }
+        other => panic!("unexpected outcome {:?}", other),
+    }
+    assert_eq!(InvocationLabel::name(entry.info.label), Some("CNodeDelete"));
+}
+
+#[test]
+fn ring_keeps_the_newest() {
+    mock::boot();
+    trace::clear();
+    for _ in 0..TRACE_ENTRIES + 3 {
+        unsafe { seL4_Yield() };
+    }
+
+    let entries = recorded();
+    assert_eq!(entries.len(), TRACE_ENTRIES);
+    assert_eq!(entries[1].seq, entries[0].seq + 1);
+    assert_eq!(entries[TRACE_ENTRIES - 1].seq, entries[0].seq + TRACE_ENTRIES - 1);
+    assert!(entries.iter().all(|e| e.syscall == SyscallId::Yield && e.outcome == Some(Outcome::Done)));
+}
@@ -0,0 +1,493 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+//! Thread management.
+//!
+//! `read_registers` and `write_registers` wrap the TCB register invocations.
+//! They transfer the first `count` registers of `seL4_UserContext`, in the
+//! order its fields are declared; a count larger than the context is clamped.
+//!
+//! There is no `ThreadBuilder` with the `mock` feature, as the mock kernel
+//! cannot start a thread at an address; use `mock::spawn` instead.
+
+use core::cmp;
+use core::mem;
+use super::*;
+#[cfg(all(feature = "tls", not(feature = "mock")))]
+use tls::TlsTemplate;
+
+/// Read the first `count` registers of `tcb`.
+///
+/// The remaining fields of the returned context are zero. With `suspend`,
+/// the thread is suspended first, so the values cannot go stale.
+pub fn read_registers(tcb: seL4_TCB, suspend: bool, count: usize)
+                      -> Result<seL4_UserContext, isize> {
+    let count = cmp::min(count, seL4_UserContextWords);
+    let mut regs: seL4_UserContext = Default::default();
+    let err = unsafe {
+        seL4_TCB_ReadRegisters(tcb, suspend as u8, 0, count as seL4_Word, &mut regs)
+    };
+    if err != 0 {
+        return Err(err);
+    }
+
+    /* The stub unmarshals every word, whether the kernel sent it or not. */
+    for w in regs.as_words_mut()[count..].iter_mut() {
+        *w = 0;
+    }
+    Ok(regs)
+}
+
+/// Read all registers of `tcb`.
+pub fn read_all_registers(tcb: seL4_TCB, suspend: bool) -> Result<seL4_UserContext, isize> {
+    read_registers(tcb, suspend, seL4_UserContextWords)
+}
+
+/// Write the first `count` registers of `regs` to `tcb`.
+///
+/// With `resume`, the thread is resumed afterwards.
+pub fn write_registers(tcb: seL4_TCB, resume: bool, count: usize, regs: &seL4_UserContext)
+                       -> Result<(), isize> {
+    let count = cmp::min(count, seL4_UserContextWords);
+    /* The stub takes a mutable pointer but only reads through it. */
+    let mut regs = *regs;
+    let err = unsafe {
+        seL4_TCB_WriteRegisters(tcb, resume as u8, 0, count as seL4_Word, &mut regs)
+    };
+    if err != 0 { Err(err) } else { Ok(()) }
+}
+
+/// Write all registers of `regs` to `tcb`.
+pub fn write_all_registers(tcb: seL4_TCB, resume: bool, regs: &seL4_UserContext)
+                           -> Result<(), isize> {
+    write_registers(tcb, resume, seL4_UserContextWords, regs)
+}
+
+#[allow(dead_code)]
+fn assert_context_size() {
+    /* seL4_UserContext::as_words relies on there being no padding. */
+    let _: [u8; seL4_UserContextWords * 4] = unsafe { mem::transmute(seL4_UserContext::default()) };
+}
+
+/* Passed to a new thread at the top of its stack. */
+#[cfg(not(feature = "mock"))]
+#[repr(C)]
+struct StartInfo {
+    entry: fn(seL4_Word),
+    arg: seL4_Word,
+    tcb: seL4_TCB,
+    ipc_buffer: seL4_Word,
+}
+
+#[cfg(not(feature = "mock"))]
+extern "C" fn thread_start(info: *const StartInfo) -> ! {
+    unsafe {
+        let info = &*info;
+        init_ipc_buffer(info.ipc_buffer as *mut seL4_IPCBuffer);
+        (info.entry)(info.arg);
+        seL4_TCB_Suspend(info.tcb);
+    }
+    loop { }
+}
+
+/// Make `seL4_GetIPCBuffer` return `buffer` on the calling thread.
+///
+/// Every thread has to do this once before using the IPC buffer through
+/// `seL4_GetIPCBuffer`; threads started by `ThreadBuilder` already have.
+#[cfg(all(target_arch = "x86", not(feature = "mock")))]
+pub unsafe fn init_ipc_buffer(buffer: *mut seL4_IPCBuffer) {
+    /* %gs already points at the IPC buffer the TCB was configured with;
+     * seL4_GetIPCBuffer reads its address back out of the user data word. */
+    seL4_SetUserData(buffer as seL4_Word);
+}
+
+/// Make `seL4_GetIPCBuffer` return `buffer` on the calling thread.
+///
+/// Every thread has to do this once before using the IPC buffer through
+/// `seL4_GetIPCBuffer`; threads started by `ThreadBuilder` already have.
+#[cfg(all(target_arch = "arm", not(feature = "mock")))]
+pub unsafe fn init_ipc_buffer(_buffer: *mut seL4_IPCBuffer) {
+    /* The kernel publishes the IPC buffer address in the globals frame. */
+}
+
+/// Make `seL4_GetIPCBuffer` return `buffer` on the calling thread.
+///
+/// Nothing to do with the mock kernel, which gives every host thread its
+/// own buffer.
+#[cfg(feature = "mock")]
+pub unsafe fn init_ipc_buffer(_buffer: *mut seL4_IPCBuffer) {
+}
+
+/// An IPC buffer frame mapped into a VSpace.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpcBuffer {
+    /// Where the buffer is mapped.
+    pub vaddr: seL4_Word,
+    /// Cap to the frame, for `seL4_TCB_Configure` or `seL4_TCB_SetIPCBuffer`.
+    pub frame: seL4_CPtr,
+}
+
+impl IpcBuffer {
+    /// Retype a frame from `untyped` into `slot` of the root task's CNode and
+    /// map it at `vaddr` in `vspace`.
+    ///
+    /// If there is no page table covering `vaddr` yet, one is retyped into
+    /// `pt_slot` and mapped first; without a `pt_slot` the missing page
+    /// table is an error. `vaddr` must be page aligned.
+    #[cfg(any(target_arch = "x86", feature = "mock"))]
+    pub fn allocate(untyped: seL4_Untyped, slot: seL4_CPtr, pt_slot: Option<seL4_CPtr>,
+                    vspace: seL4_IA32_PageDirectory, vaddr: seL4_Word) -> Result<IpcBuffer, isize> {
+        assert!(vaddr & ((1 << seL4_PageBits) - 1) == 0, "IPC buffer must be page aligned");
+
+        try!(retype(untyped, seL4_ObjectType::seL4_IA32_4K, slot));
+        let map = || unsafe {
+            seL4_IA32_Page_Map(slot, vspace, vaddr, seL4_CapRights::AllRights,
+                               seL4_IA32_VMAttributes::WriteBack)
+        };
+
+        let mut err = map();
+        let mut pt_retyped = None;
+        if err == seL4_Error::seL4_FailedLookup as isize {
+            if let Some(pt) = pt_slot {
+                err = match retype(untyped, seL4_ObjectType::seL4_IA32_PageTableObject, pt) {
+                    Ok(()) => {
+                        pt_retyped = Some(pt);
+                        unsafe {
+                            seL4_IA32_PageTable_Map(pt, vspace, vaddr, seL4_IA32_VMAttributes::WriteBack)
+                        }
+                    }
+                    Err(err) => err,
+                };
+                if err == 0 {
+                    err = map();
+                }
+            }
+        }
+        if err != 0 {
+            /* Give back what this call retyped. */
+            if let Some(pt) = pt_retyped {
+                delete(pt);
+            }
+            delete(slot);
+            return Err(err);
+        }
+
+        Ok(IpcBuffer { vaddr: vaddr, frame: slot })
+    }
+
+    /// A page-aligned IPC buffer inside `pages`, a static of the root task
+    /// of at least `IPC_BUFFER_PAGES` bytes, with the image frame backing it.
+    ///
+    /// Relies on the image starting at `__executable_start`, as it does with
+    /// the default GNU ld script. The frame cap is the root task's own, so
+    /// the buffer must not also be used as ordinary memory.
+    #[cfg(not(feature = "mock"))]
+    pub fn in_image(bootinfo: &seL4_BootInfo, pages: &'static mut [u8])
+                    -> Result<IpcBuffer, &'static str> {
+        assert!(pages.len() >= IPC_BUFFER_PAGES, "IPC buffer pages too small");
+        let page = 1 << seL4_PageBits;
+        let image_start = unsafe { &__executable_start as *const u8 as usize } & !(page - 1);
+        let buffer = (pages.as_ptr() as usize + page - 1) & !(page - 1);
+        let frame = bootinfo.userImageFrames.start + ((buffer - image_start) / page) as seL4_Word;
+        if frame >= bootinfo.userImageFrames.end {
+            return Err("ipc-buffer-frame");
+        }
+        Ok(IpcBuffer { vaddr: buffer as seL4_Word, frame: frame })
+    }
+
+    /// Make this the IPC buffer of `tcb`.
+    ///
+    /// The thread itself still has to call `init_ipc_buffer`.
+    pub fn set_for(&self, tcb: seL4_TCB) -> Result<(), isize> {
+        match unsafe { seL4_TCB_SetIPCBuffer(tcb, self.vaddr, self.frame) } {
+            0 => Ok(()),
+            err => Err(err),
+        }
+    }
+}
+
+/// Bytes of a static to set aside for `IpcBuffer::in_image`: two pages, so a
+/// page-aligned IPC buffer always fits inside.
+pub const IPC_BUFFER_PAGES: usize = 2 << seL4_PageBits;
+
+#[cfg(not(feature = "mock"))]
+extern {
+    static __executable_start: u8;
+}
+
+/// Retype one object of each of `types` into consecutive slots of the root
+/// task's CNode, starting at `first`, all from the first untyped in
+/// `bootinfo` of at least `2^min_bits` bytes. The slots must lie in the
+/// bootinfo's empty region.
+///
+/// Returns the untyped used, so that more can be taken from it later.
+pub fn retype_from_bootinfo(bootinfo: &seL4_BootInfo, min_bits: usize, types: &[seL4_ObjectType],
+                            first: seL4_CPtr) -> Result<seL4_Untyped, &'static str> {
+    let untyped = match (0..(bootinfo.untyped.end - bootinfo.untyped.start) as usize)
+                            .find(|&i| bootinfo.untypedSizeBitsList[i] as usize >= min_bits) {
+        Some(i) => bootinfo.untyped.start + i as seL4_Word,
+        None => return Err("no-untyped"),
+    };
+    if first < bootinfo.empty.start || first + types.len() as seL4_Word > bootinfo.empty.end {
+        return Err("no-slots");
+    }
+    for (i, &type_) in types.iter().enumerate() {
+        if retype(untyped, type_, first + i as seL4_CPtr).is_err() {
+            return Err("retype");
+        }
+    }
+    Ok(untyped)
+}
+
+fn delete(slot: seL4_CPtr) {
+    unsafe { seL4_CNode_Delete(seL4_Cap::seL4_CapInitThreadCNode as seL4_CNode, slot, 32) };
+}
+
+/* Retype into the root CNode, where a slot's index is also its cptr. */
+fn retype(untyped: seL4_Untyped, type_: seL4_ObjectType, slot: seL4_CPtr) -> Result<(), isize> {
+    let root = seL4_Cap::seL4_CapInitThreadCNode as seL4_CNode;
+    match unsafe { seL4_Untyped_Retype(untyped, type_ as seL4_Word, 0, root, 0, 0, slot as isize, 1) } {
+        0 => Ok(()),
+        err => Err(err),
+    }
+}
+
+/// Everything needed to start a new thread in the caller's address space.
+///
+/// ```ignore
+/// let thread = try!(ThreadBuilder::new(worker, 0)
+///                       .stack(unsafe { &mut WORKER_STACK })
+///                       .ipc_buffer(buffer)
+///                       .priority(100)
+///                       .fault_endpoint(fault_ep)
+///                       .spawn(untyped, slot));
+/// ```
+///
+/// The thread runs `entry(arg)` and suspends itself if `entry` returns. By
+/// default it shares the root task's CSpace and VSpace. Its TCB cap is
+/// placed in the root task's CNode, so that the caller can invoke it.
+#[cfg(not(feature = "mock"))]
+pub struct ThreadBuilder {
+    entry: fn(seL4_Word),
+    arg: seL4_Word,
+    stack: Option<&'static mut [u8]>,
+    #[cfg(feature = "tls")]
+    tls: Option<&'static mut [u8]>,
+    ipc_buffer: seL4_Word,
+    ipc_buffer_frame: seL4_CPtr,
+    priority: u8,
+    fault_endpoint: seL4_CPtr,
+    cspace: seL4_CNode,
+    vspace: seL4_CPtr,
+    name: Option<&'static str>,
+}
+
+#[cfg(not(feature = "mock"))]
+impl ThreadBuilder {
+    pub fn new(entry: fn(seL4_Word), arg: seL4_Word) -> ThreadBuilder {
+        ThreadBuilder {
+            entry: entry,
+            arg: arg,
+            stack: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+            ipc_buffer: 0,
+            ipc_buffer_frame: seL4_Cap::seL4_CapNull as seL4_CPtr,
+            priority: 0,
+            fault_endpoint: seL4_Cap::seL4_CapNull as seL4_CPtr,
+            cspace: seL4_Cap::seL4_CapInitThreadCNode as seL4_CNode,
+            vspace: seL4_Cap::seL4_CapInitThreadVSpace as seL4_CPtr,
+            name: None,
+        }
+    }
+
+    /// Memory for the thread's stack. Required.
+    pub fn stack(mut self, stack: &'static mut [u8]) -> ThreadBuilder {
+        self.stack = Some(stack);
+        self
+    }
+
+    /// Memory for the thread's TLS block, at least
+    /// `TlsTemplate::block_size` bytes. Without one, the thread must not
+    /// touch thread-locals. Needs the `tls` feature.
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, block: &'static mut [u8]) -> ThreadBuilder {
+        self.tls = Some(block);
+        self
+    }
+
+    /// The thread's IPC buffer, for instance from `IpcBuffer::allocate`.
+    pub fn ipc_buffer(mut self, buffer: IpcBuffer) -> ThreadBuilder {
+        self.ipc_buffer = buffer.vaddr;
+        self.ipc_buffer_frame = buffer.frame;
+        self
+    }
+
+    pub fn priority(mut self, priority: u8) -> ThreadBuilder {
+        self.priority = priority;
+        self
+    }
+
+    /// Cap the kernel sends the thread's faults to, looked up in the
+    /// thread's CSpace.
+    pub fn fault_endpoint(mut self, ep: seL4_CPtr) -> ThreadBuilder {
+        self.fault_endpoint = ep;
+        self
+    }
+
+    /// Root of the thread's CSpace.
+    pub fn cspace(mut self, root: seL4_CNode) -> ThreadBuilder {
+        self.cspace = root;
+        self
+    }
+
+    /// Root of the thread's VSpace.
+    pub fn vspace(mut self, root: seL4_CPtr) -> ThreadBuilder {
+        self.vspace = root;
+        self
+    }
+
+    /// Name for the thread in kernel debug output. Ignored without
+    /// `SEL4_DEBUG`.
+    pub fn name(mut self, name: &'static str) -> ThreadBuilder {
+        self.name = Some(name);
+        self
+    }
+
+    /// Create the thread from `untyped`, putting its TCB cap at `slot` of the
+    /// root task's CNode, and start it.
+    ///
+    /// Returns the seL4 error code of the first invocation that fails, after
+    /// deleting the TCB cap again if it was created. Panics if no stack was
+    /// given.
+    pub fn spawn(mut self, untyped: seL4_Untyped, slot: seL4_CPtr) -> Result<ThreadHandle, isize> {
+        let stack = self.stack.take().expect("ThreadBuilder needs a stack");
+
+        let root = seL4_Cap::seL4_CapInitThreadCNode as seL4_CNode;
+        try!(retype(untyped, seL4_ObjectType::seL4_TCBObject, slot));
+        let thread = ThreadHandle { tcb: slot };
+
+        let err = unsafe {
+            seL4_TCB_Configure(thread.tcb, self.fault_endpoint as seL4_Word, self.priority,
+                               self.cspace, seL4_CapData { words: [0] },
+                               self.vspace, seL4_CapData { words: [0] },
+                               self.ipc_buffer, self.ipc_buffer_frame)
+        };
+        if err != 0 {
+            unsafe { seL4_CNode_Delete(root, slot, 32) };
+            return Err(err);
+        }
+
+        if let Some(name) = self.name {
+            set_name(thread.tcb, name);
+        }
+
+        /* Put the start info at the top of the stack, below it a fake
+         * return address and the argument to thread_start. */
+        let top = (stack.as_mut_ptr() as usize + stack.len()) & !0xf;
+        let info = (top - mem::size_of::<StartInfo>()) & !0xf;
+        unsafe {
+            *(info as *mut StartInfo) = StartInfo {
+                entry: self.entry,
+                arg: self.arg,
+                tcb: thread.tcb,
+                ipc_buffer: self.ipc_buffer,
+            };
+        }
+
+        let mut regs = seL4_UserContext::new_thread(thread_start as seL4_Word, 0);
+        set_start_argument(&mut regs, info);
+        self.set_tls(&mut regs);
+        if let Err(err) = write_all_registers(thread.tcb, true, &regs) {
+            unsafe { seL4_CNode_Delete(root, slot, 32) };
+            return Err(err);
+        }
+
+        Ok(thread)
+    }
+
+    #[cfg(feature = "tls")]
+    fn set_tls(&mut self, regs: &mut seL4_UserContext) {
+        if let Some(block) = self.tls.take() {
+            if let Some(template) = TlsTemplate::from_image() {
+                regs.set_tls_base(template.init(block));
+            }
+        }
+    }
+
+    #[cfg(not(feature = "tls"))]
+    fn set_tls(&mut self, _regs: &mut seL4_UserContext) { }
+}
+
+#[cfg(all(target_arch = "x86", not(feature = "mock")))]
+fn set_start_argument(regs: &mut seL4_UserContext, info: usize) {
+    /* cdecl: the argument sits above the return address, and the i386 ABI
+     * wants it 16-byte aligned, as if a call had just pushed the return
+     * address. info is 16-byte aligned already. */
+    let arg = info - 16;
+    let sp = arg - mem::size_of::<seL4_Word>();
+    unsafe {
+        *(sp as *mut seL4_Word) = 0;
+        *(arg as *mut seL4_Word) = info as seL4_Word;
+    }
+    regs.set_sp(sp as seL4_Word);
+}
+
+#[cfg(all(target_arch = "arm", not(feature = "mock")))]
+fn set_start_argument(regs: &mut seL4_UserContext, info: usize) {
+    regs.set_sp(info as seL4_Word);
+    regs.set_arg(0, info as seL4_Word);
+}
+
+#[cfg(all(feature = "SEL4_DEBUG", not(feature = "mock")))]
+fn set_name(tcb: seL4_TCB, name: &str) {
+    ::debug::name_thread(tcb, name);
+}
+
+#[cfg(all(not(feature = "SEL4_DEBUG"), not(feature = "mock")))]
+fn set_name(_tcb: seL4_TCB, _name: &str) { }
+
+/// A thread started by `ThreadBuilder`.
+#[derive(Debug)]
+pub struct ThreadHandle {
+    tcb: seL4_TCB,
+}
+
+impl ThreadHandle {
+    /// The thread's TCB cap.
+    pub fn tcb(&self) -> seL4_TCB {
+        self.tcb
+    }
+
+    pub fn suspend(&self) -> Result<(), isize> {
+        match unsafe { seL4_TCB_Suspend(self.tcb) } {
+            0 => Ok(()),
+            err => Err(err),
+        }
+    }
+
+    pub fn resume(&self) -> Result<(), isize> {
+        match unsafe { seL4_TCB_Resume(self.tcb) } {
+            0 => Ok(()),
+            err => Err(err),
+        }
+    }
+
+    /// Stop the thread and delete its TCB cap. The TCB itself is freed when
+    /// its last cap goes.
+    pub fn destroy(self) -> Result<(), isize> {
+        try!(self.suspend());
+        match unsafe { seL4_CNode_Delete(seL4_Cap::seL4_CapInitThreadCNode as seL4_CNode,
+                                         self.tcb, 32) } {
+            0 => Ok(()),
+            err => Err(err),
+        }
+    }
+}
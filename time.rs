@@ -0,0 +1,214 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+//! Cycle counter and timing.
+//!
+//! `cycles` reads the CPU cycle counter: `rdtsc` on x86, the PMU cycle
+//! counter (PMCCNTR) on ARM. The ARM counter is only readable from user
+//! level if the kernel enables user access to the PMU, and it is 32 bits
+//! wide, so durations there wrap after 2^32 cycles.
+//!
+//! Converting cycles to nanoseconds needs the counter frequency, which has to
+//! be given with `set_frequency` or measured with `calibrate` first.
+
+use core::ops::{Add, Sub};
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+const COUNTER_MASK: u64 = !0;
+
+#[cfg(target_arch = "arm")]
+const COUNTER_MASK: u64 = 0xffff_ffff;
+
+static mut FREQUENCY: u64 = 0;
+
+/// Read the cycle counter.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[inline(always)]
+pub fn cycles() -> u64 {
+    let lo: u32;
+    let hi: u32;
+    unsafe {
+        asm!("rdtsc" : "={eax}"(lo), "={edx}"(hi) : : : "volatile");
+    }
+    ((hi as u64) << 32) | lo as u64
+}
+
+/// Read the cycle counter.
+#[cfg(target_arch = "arm")]
+#[inline(always)]
+pub fn cycles() -> u64 {
+    let ccnt: u32;
+    unsafe {
+        asm!("mrc p15, 0, $0, c9, c13, 0" : "=r"(ccnt) : : : "volatile");
+    }
+    ccnt as u64
+}
+
+/// Set the cycle counter frequency used for conversions to nanoseconds.
+pub fn set_frequency(hz: u64) {
+    unsafe { FREQUENCY = hz };
+}
+
+/// The cycle counter frequency, or 0 if it has not been set.
+pub fn frequency() -> u64 {
+    unsafe { FREQUENCY }
+}
+
+/// Measure the cycle counter frequency.
+///
+/// `wait` must block for `nanos` nanoseconds against some other clock, for
+/// instance by waiting for a timer interrupt. Returns the measured frequency,
+/// which is also stored for later conversions. Panics if `nanos` is 0.
+pub fn calibrate<F: FnOnce()>(wait: F, nanos: u64) -> u64 {
+    assert!(nanos != 0, "cannot calibrate against a zero-length wait");
+    let start = Instant::now();
+    wait();
+    let elapsed = start.elapsed().cycles();
+    let hz = mul_div(elapsed, 1_000_000_000, nanos);
+    set_frequency(hz);
+    hz
+}
+
+/// Run `f` and return its result along with how long it took.
+pub fn measure<R, F: FnOnce() -> R>(f: F) -> (R, Duration) {
+    let start = Instant::now();
+    let ret = f();
+    (ret, start.elapsed())
+}
+
+/* a * b / c, computed on the full 128-bit product. Saturates if the
+ * quotient does not fit in 64 bits. */
+fn mul_div(a: u64, b: u64, c: u64) -> u64 {
+    let (hi, lo) = mul_wide(a, b);
+    if hi >= c {
+        return !0;
+    }
+    /* Long division, one bit of lo at a time; rem < c throughout. */
+    let mut rem = hi;
+    let mut quot = 0;
+    for i in (0..64).rev() {
+        let carry = rem >> 63;
+        rem = (rem << 1) | ((lo >> i) & 1);
+        quot <<= 1;
+        if carry != 0 || rem >= c {
+            rem = rem.wrapping_sub(c);
+            quot |= 1;
+        }
+    }
+    quot
+}
+
+/* The 128-bit product of a and b, as (high, low) halves. */
+fn mul_wide(a: u64, b: u64) -> (u64, u64) {
+    const LOW: u64 = 0xffff_ffff;
+    let (ah, al) = (a >> 32, a & LOW);
+    let (bh, bl) = (b >> 32, b & LOW);
+    let ll = al * bl;
+    let lh = al * bh;
+    let hl = ah * bl;
+    let mid = (ll >> 32) + (lh & LOW) + (hl & LOW);
+    ((ah * bh) + (lh >> 32) + (hl >> 32) + (mid >> 32), (ll & LOW) | (mid << 32))
+}
+
+/// A point in time, as a cycle counter reading.
+///
+/// Comparisons order the raw readings, so they are only meaningful while the
+/// counter has not wrapped in between, which on ARM is a matter of seconds.
+/// `duration_since` and `elapsed` subtract modulo the counter width and stay
+/// correct across one wrap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Instant {
+    cycles: u64,
+}
+
+impl Instant {
+    pub fn now() -> Instant {
+        Instant { cycles: cycles() }
+    }
+
+    /// Time passed between `earlier` and `self`.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_cycles(self.cycles.wrapping_sub(earlier.cycles) & COUNTER_MASK)
+    }
+
+    /// Time passed since `self`.
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, rhs: Duration) -> Instant {
+        Instant { cycles: self.cycles.wrapping_add(rhs.cycles) & COUNTER_MASK }
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, rhs: Instant) -> Duration {
+        self.duration_since(rhs)
+    }
+}
+
+/// A span of time, counted in cycles.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Duration {
+    cycles: u64,
+}
+
+impl Duration {
+    pub fn from_cycles(cycles: u64) -> Duration {
+        Duration { cycles: cycles }
+    }
+
+    /// Convert from nanoseconds. Panics if the frequency is not known.
+    pub fn from_nanos(nanos: u64) -> Duration {
+        Duration { cycles: mul_div(nanos, checked_frequency(), 1_000_000_000) }
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Convert to nanoseconds. Panics if the frequency is not known.
+    pub fn nanos(&self) -> u64 {
+        mul_div(self.cycles, 1_000_000_000, checked_frequency())
+    }
+
+    /// Convert to microseconds. Panics if the frequency is not known.
+    pub fn micros(&self) -> u64 {
+        self.nanos() / 1_000
+    }
+}
+
+impl Add for Duration {
+    type Output = Duration;
+
+    fn add(self, rhs: Duration) -> Duration {
+        Duration { cycles: self.cycles + rhs.cycles }
+    }
+}
+
+impl Sub for Duration {
+    type Output = Duration;
+
+    fn sub(self, rhs: Duration) -> Duration {
+        Duration { cycles: self.cycles - rhs.cycles }
+    }
+}
+
+fn checked_frequency() -> u64 {
+    let hz = frequency();
+    assert!(hz != 0, "cycle counter frequency is not set");
+    hz
+}
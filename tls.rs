@@ -0,0 +1,163 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+//! Thread-local storage.
+//!
+//! Each thread that uses `#[thread_local]` statics needs its own TLS block,
+//! initialised from the PT_TLS segment of the executable, and its thread
+//! pointer set to that block. `ThreadBuilder::tls` does both for new threads.
+//!
+//! This module needs the `tls` feature. On x86 the kernel bases one segment
+//! at the thread's `tls_base` and another at its IPC buffer, and starts
+//! threads with %gs on the IPC buffer. The compiler reaches thread-locals
+//! through %gs, so the feature swaps the two: threads run with %gs on the
+//! TLS segment and the IPC buffer accessors use %fs (see
+//! `seL4_UserContext::new_thread`; the runtime's `_start` does the same for
+//! the initial thread). This changes the ABI between the crate
+//! and its callers, so code built without the feature, such as C linked
+//! against libsel4, cannot use the IPC buffer of a thread started with it.
+//!
+//! The initial thread cannot set its own `tls_base`, so thread-locals are
+//! only usable on threads started with a TLS block; the root task's own
+//! thread must not touch them even with the feature.
+
+use core::cmp;
+use core::mem;
+use core::ptr;
+use core::slice;
+use super::*;
+
+const PT_TLS: u32 = 7;
+const ELFMAG: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+
+#[repr(C)]
+struct Elf32_Ehdr {
+    e_ident: [u8; 16],
+    e_type: u16,
+    e_machine: u16,
+    e_version: u32,
+    e_entry: u32,
+    e_phoff: u32,
+    e_shoff: u32,
+    e_flags: u32,
+    e_ehsize: u16,
+    e_phentsize: u16,
+    e_phnum: u16,
+    e_shentsize: u16,
+    e_shnum: u16,
+    e_shstrndx: u16,
+}
+
+#[repr(C)]
+struct Elf32_Phdr {
+    p_type: u32,
+    p_offset: u32,
+    p_vaddr: u32,
+    p_paddr: u32,
+    p_filesz: u32,
+    p_memsz: u32,
+    p_flags: u32,
+    p_align: u32,
+}
+
+extern {
+    static __executable_start: Elf32_Ehdr;
+}
+
+/* The thread control block the thread pointer points at. On x86 it holds
+ * a pointer to itself; on ARM it is two reserved words. */
+#[cfg(target_arch = "x86")]
+const TCB_SIZE: usize = 4;
+#[cfg(target_arch = "arm")]
+const TCB_SIZE: usize = 8;
+
+fn round_up(x: usize, align: usize) -> usize {
+    (x + align - 1) & !(align - 1)
+}
+
+/// The initial contents of every thread's TLS block.
+#[derive(Clone, Copy, Debug)]
+pub struct TlsTemplate {
+    data: *const u8,
+    filesz: usize,
+    memsz: usize,
+    align: usize,
+}
+
+impl TlsTemplate {
+    /// Find the PT_TLS segment of the running executable.
+    ///
+    /// Relies on the ELF headers being loaded at `__executable_start`, as
+    /// they are with the default GNU ld script. Returns `None` if they are
+    /// not there or the executable has no thread-locals.
+    pub fn from_image() -> Option<TlsTemplate> {
+        unsafe {
+            let ehdr = &__executable_start;
+            if ehdr.e_ident[..4] != ELFMAG[..] {
+                return None;
+            }
+            let base = ehdr as *const Elf32_Ehdr as usize;
+            let phdrs = slice::from_raw_parts((base + ehdr.e_phoff as usize) as *const Elf32_Phdr,
+                                              ehdr.e_phnum as usize);
+            phdrs.iter().find(|ph| ph.p_type == PT_TLS).map(|ph| TlsTemplate {
+                data: ph.p_vaddr as *const u8,
+                filesz: ph.p_filesz as usize,
+                memsz: ph.p_memsz as usize,
+                align: cmp::max(ph.p_align as usize, mem::align_of::<seL4_Word>()),
+            })
+        }
+    }
+
+    /// Bytes a TLS block must have, whatever its alignment.
+    pub fn block_size(&self) -> usize {
+        round_up(self.memsz, self.align) + TCB_SIZE + self.align
+    }
+
+    /// Lay out a fresh copy of the TLS data in `block`.
+    ///
+    /// Returns the thread pointer to use as the thread's `tls_base`. Panics
+    /// if `block` is smaller than `block_size`.
+    #[cfg(target_arch = "x86")]
+    pub fn init(&self, block: &mut [u8]) -> seL4_Word {
+        /* Variant II: the data sits just below the thread pointer. */
+        assert!(block.len() >= self.block_size(), "TLS block too small");
+        let end = block.as_mut_ptr() as usize + block.len();
+        let tp = (end - TCB_SIZE) & !(self.align - 1);
+        let data = tp - round_up(self.memsz, self.align);
+        unsafe {
+            self.copy_to(data as *mut u8);
+            *(tp as *mut usize) = tp;
+        }
+        tp as seL4_Word
+    }
+
+    /// Lay out a fresh copy of the TLS data in `block`.
+    ///
+    /// Returns the thread pointer to use as the thread's `tls_base`. Panics
+    /// if `block` is smaller than `block_size`.
+    #[cfg(target_arch = "arm")]
+    pub fn init(&self, block: &mut [u8]) -> seL4_Word {
+        /* Variant I: the data follows the TCB the thread pointer points at. */
+        assert!(block.len() >= self.block_size(), "TLS block too small");
+        let start = block.as_mut_ptr() as usize;
+        let data = round_up(start + TCB_SIZE, self.align);
+        let tp = data - TCB_SIZE;
+        unsafe {
+            ptr::write_bytes(tp as *mut u8, 0, TCB_SIZE);
+            self.copy_to(data as *mut u8);
+        }
+        tp as seL4_Word
+    }
+
+    unsafe fn copy_to(&self, data: *mut u8) {
+        ptr::copy_nonoverlapping(self.data, data, self.filesz);
+        ptr::write_bytes(data.offset(self.filesz as isize), 0, self.memsz - self.filesz);
+    }
+}
@@ -37,6 +37,19 @@ pub enum InvocationLabel {
     {{label}} = {{loop.index+1}},
     {{endfor}}
 }
+
+impl InvocationLabel {
+    /// The name of the invocation with label `label`, if there is one.
+    pub fn name(label: seL4_Word) -> Option<&'static str> {
+        static NAMES: [&'static str; {{len(invocations) + 1}}] = [
+            "InvalidInvocation",
+            {{for label in invocations}}
+            "{{label}}",
+            {{endfor}}
+        ];
+        NAMES.get(label as usize).cloned()
+    }
+}
 """
 
 def parse_args():
@@ -75,7 +88,7 @@ def generate(args, invocations):
 if __name__ == "__main__":
     args = parse_args()
 
-    invocations = chain.from_iterable(parse_xml(xml) for xml in args.files)
+    invocations = list(chain.from_iterable(parse_xml(xml) for xml in args.files))
 
     generate(args, invocations)
 
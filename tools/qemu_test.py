@@ -0,0 +1,208 @@
+#!/usr/bin/env python
+#
+# Copyright (c) 2015 The Robigalia Project Developers
+# Licensed under the Apache License, Version 2.0
+# <LICENSE-APACHE or
+# http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+# license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+# at your option. All files in the project carrying such
+# notice may not be copied, modified, or distributed except
+# according to those terms.
+#
+
+# seL4 root task test runner
+# ==========================
+#
+# Builds the crate for a seL4 target from targets/, links each test root
+# task source against it, boots the result under QEMU with the given kernel
+# image and reads the serial console.
+#
+# A test root task reports its results in TAP, as the harness in testing.rs
+# prints them: a plan line `1..N`, then one `ok N - description` or
+# `not ok N - description` line per test. It passes if every planned test
+# printed `ok`. A `Bail out!` line, a panic message at the start of a line,
+# the timeout running out, or QEMU exiting before the plan is complete all
+# fail it. Other console output is ignored, so the root task is free to
+# print diagnostics.
+#
+# Usage: qemu_test.py -a x86 -k path/to/kernel.elf tests/root/*.rs
+#
+# The kernel image can also be given in SEL4_KERNEL. It must be built with
+# the kernel's debug printing enabled. Serial logs are kept in
+# target/qemu/<name>.log, where they can also be fed to replay::parse.
+
+from __future__ import print_function
+
+import argparse
+import os
+import re
+import subprocess
+import sys
+import threading
+import time
+
+try:
+    import queue
+except ImportError:
+    import Queue as queue
+
+# Per arch: (target JSON name, QEMU command line before the images).
+# ARM goes here once the crate has an ARM syscall layer (arch/arm.rs): its
+# root task has to be packaged with the kernel by the seL4 elfloader and
+# booted with qemu-system-arm -M kzm.
+ARCHES = {
+    'x86': ('i686-sel4-unknown',
+            ['qemu-system-i386', '-m', '512', '-nographic', '-no-reboot']),
+}
+
+# The crate features a test root task needs.
+FEATURES = ['runtime', 'panic_handler', 'SEL4_DEBUG']
+
+PLAN = re.compile(r'^1\.\.(\d+)')
+RESULT = re.compile(r'^(not )?ok\b\s*(\d*)\s*-?\s*(.*)$')
+BAIL = re.compile(r'^Bail out!\s*(.*)$')
+PANIC = re.compile(r'^panicked at ')
+
+def run(cmd, **kwargs):
+    print(' '.join(cmd), file=sys.stderr)
+    if subprocess.call(cmd, **kwargs) != 0:
+        sys.exit('%s failed' % cmd[0])
+
+def build_crate(target, release):
+    cmd = ['cargo', 'build', '--target', 'targets/%s.json' % target,
+           '--features', ' '.join(FEATURES)]
+    if release:
+        cmd.append('--release')
+    run(cmd)
+    return os.path.join('target', target, 'release' if release else 'debug')
+
+def link_root_task(src, target, libdir, out):
+    """Compile the root task in src against the crate built in libdir."""
+    cmd = ['rustc', src, '--target', 'targets/%s.json' % target,
+           '--crate-type', 'bin', '-o', out,
+           '-L', 'dependency=%s' % os.path.join(libdir, 'deps'),
+           '--extern', 'sel4_sys=%s' % os.path.join(libdir, 'libsel4_sys.rlib'),
+           # _start comes from the crate's runtime, which sel4_root_task!
+           # makes the root task refer to.
+           '-C', 'link-args=-static -nostartfiles']
+    if libdir.endswith('release'):
+        cmd.append('-O')
+    run(cmd)
+
+class Results(object):
+    def __init__(self):
+        self.plan = None
+        self.results = {}
+        self.failure = None
+
+    def feed(self, line):
+        """Take one console line; returns True once the run is over."""
+        m = PLAN.match(line)
+        if m:
+            self.plan = int(m.group(1))
+        m = RESULT.match(line)
+        if m:
+            n = int(m.group(2)) if m.group(2) else len(self.results) + 1
+            self.results[n] = (m.group(1) is None, m.group(3))
+        m = BAIL.match(line)
+        if m:
+            self.failure = 'bailed out: %s' % m.group(1)
+        elif PANIC.match(line):
+            self.failure = line
+        return self.failure is not None or self.complete()
+
+    def complete(self):
+        return self.plan is not None and len(self.results) >= self.plan
+
+    def passed(self):
+        if self.failure is not None or not self.complete():
+            return False
+        return all(self.results.get(n, (False, ''))[0] for n in range(1, self.plan + 1))
+
+def read_lines(stream, lines):
+    for line in iter(stream.readline, b''):
+        lines.put(line.decode('utf-8', 'replace').rstrip('\r\n'))
+    lines.put(None)
+
+def boot(qemu, kernel, root_task, log, timeout, verbose):
+    """Boot root_task under QEMU and collect its TAP results."""
+    cmd = qemu + ['-kernel', kernel, '-initrd', root_task]
+    print(' '.join(cmd), file=sys.stderr)
+    proc = subprocess.Popen(cmd, stdin=subprocess.PIPE, stdout=subprocess.PIPE)
+    lines = queue.Queue()
+    reader = threading.Thread(target=read_lines, args=(proc.stdout, lines))
+    reader.daemon = True
+    reader.start()
+
+    results = Results()
+    deadline = time.time() + timeout
+    try:
+        while True:
+            try:
+                line = lines.get(timeout=max(deadline - time.time(), 0))
+            except queue.Empty:
+                results.failure = 'no result within %d seconds' % timeout
+                break
+            if line is None:
+                if not results.complete():
+                    results.failure = 'QEMU exited'
+                break
+            log.write(line + '\n')
+            if verbose:
+                print(line)
+            if results.feed(line):
+                break
+    finally:
+        if proc.poll() is None:
+            proc.kill()
+        proc.wait()
+    return results
+
+def report(name, results):
+    for n in sorted(results.results):
+        ok, desc = results.results[n]
+        print('    %s %d %s' % ('ok' if ok else 'not ok', n, desc))
+    if results.failure is not None:
+        print('    %s' % results.failure)
+    elif not results.complete():
+        print('    no test plan printed' if results.plan is None else
+              '    %d of %d tests reported' % (len(results.results), results.plan))
+    print('test %s ... %s' % (name, 'ok' if results.passed() else 'FAILED'))
+
+def main():
+    parser = argparse.ArgumentParser(description='Run seL4 root task tests under QEMU')
+    parser.add_argument('-a', '--arch', required=True, choices=sorted(ARCHES.keys()))
+    parser.add_argument('-k', '--kernel', default=os.environ.get('SEL4_KERNEL'),
+                        help='seL4 kernel image (default: $SEL4_KERNEL)')
+    parser.add_argument('-t', '--timeout', type=int, default=60,
+                        help='seconds to give each root task')
+    parser.add_argument('--release', action='store_true', help='build with optimizations')
+    parser.add_argument('-v', '--verbose', action='store_true', help='echo the serial console')
+    parser.add_argument('tests', nargs='+', help='root task sources')
+    args = parser.parse_args()
+    if args.kernel is None:
+        parser.error('no kernel image given and SEL4_KERNEL is not set')
+
+    target, qemu = ARCHES[args.arch]
+    libdir = build_crate(target, args.release)
+    outdir = os.path.join('target', 'qemu')
+    if not os.path.isdir(outdir):
+        os.makedirs(outdir)
+
+    failed = []
+    for src in args.tests:
+        name = os.path.splitext(os.path.basename(src))[0]
+        root_task = os.path.join(outdir, name)
+        link_root_task(src, target, libdir, root_task)
+        with open(root_task + '.log', 'w') as log:
+            results = boot(qemu, args.kernel, root_task, log, args.timeout, args.verbose)
+        report(name, results)
+        if not results.passed():
+            failed.append(name)
+
+    print('\n%d passed, %d failed' % (len(args.tests) - len(failed), len(failed)))
+    if failed:
+        sys.exit(1)
+
+if __name__ == '__main__':
+    main()
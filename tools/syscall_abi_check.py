@@ -0,0 +1,231 @@
+#!/usr/bin/env python
+#
+# Copyright (c) 2015 The Robigalia Project Developers
+# Licensed under the Apache License, Version 2.0
+# <LICENSE-APACHE or
+# http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+# license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+# at your option. All files in the project carrying such
+# notice may not be copied, modified, or distributed except
+# according to those terms.
+#
+
+# seL4 syscall stub ABI checker
+# =============================
+#
+# Checks the hand-written syscall stubs in arch/<arch>.rs against a table of
+# the kernel's syscall ABI: which syscall each stub makes, and which
+# registers carry the syscall number, the cap, the MessageInfo and the
+# register MRs in each direction. Also catches malformed operand lists (a
+# missing comma between operands) and stubs that return something other
+# than the MessageInfo the kernel handed back.
+#
+# This reads the source; nothing is executed. Only the operand lists are
+# checked, so a wrong instruction in an asm body, such as a bad move of ecx
+# into ebp, or mishandled results after the trap, get past it. The stubs
+# only really run against a kernel, as in tests/qemu.rs.
+#
+# Usage: syscall_abi_check.py -a x86 arch/x86.rs
+
+from __future__ import print_function
+
+import argparse
+import re
+import sys
+
+# Per-stub ABI: (syscall, input registers, output registers, returns info).
+# Inputs always include the syscall number register, which is checked
+# separately against the syscall name.
+
+# ia32 sysenter: eax syscall number, ebx cap in and badge out, esi
+# MessageInfo, edi MR0, ebp MR1 (passed in ecx, which is moved to ebp
+# around sysenter because ecx and edx carry the user stack and return
+# address).
+X86_SYSCALL_REG = 'ax'
+X86_INFO_REG = 'si'
+X86_SEND = set(['bx', 'si', 'di', 'cx'])
+X86_RECV = set(['bx', 'si', 'di', 'cx'])
+
+X86_ABI = {
+    'seL4_Send':               ('Send', X86_SEND, set(), False),
+    'seL4_SendWithMRs':        ('Send', X86_SEND, set(), False),
+    'seL4_NBSend':             ('NBSend', X86_SEND, set(), False),
+    'seL4_NBSendWithMRs':      ('NBSend', X86_SEND, set(), False),
+    'seL4_Reply':              ('Reply', set(['si', 'di', 'cx']), set(), False),
+    'seL4_ReplyWithMRs':       ('Reply', set(['si', 'di', 'cx']), set(), False),
+    'seL4_Notify':             ('Send', set(['bx', 'si', 'di']), set(), False),
+    'seL4_Wait':               ('Wait', set(['bx']), X86_RECV, True),
+    'seL4_WaitWithMRs':        ('Wait', set(['bx']), X86_RECV, True),
+    # ebx is only an output to tell the compiler it is clobbered.
+    'seL4_Call':               ('Call', X86_SEND, set(['bx', 'si', 'di', 'cx']), True),
+    'seL4_CallWithMRs':        ('Call', X86_SEND, set(['bx', 'si', 'di', 'cx']), True),
+    'seL4_ReplyWait':          ('ReplyWait', X86_SEND, X86_RECV, True),
+    'seL4_ReplyWaitWithMRs':   ('ReplyWait', X86_SEND, X86_RECV, True),
+    'seL4_Yield':              ('Yield', set(), set(), False),
+    'seL4_DebugPutChar':       ('DebugPutChar', set(['bx']), set(), False),
+    'seL4_DebugHalt':          ('DebugHalt', set(), set(), False),
+    'seL4_DebugSnapshot':      ('DebugSnapshot', set(), set(), False),
+    'seL4_DebugCapIdentify':   ('DebugCapIdentify', set(['bx']), set(['bx']), False),
+    'seL4_DebugNameThread':    ('DebugNameThread', set(['bx']), set(), False),
+    'seL4_DebugRun':           ('DebugRun', set(['bx', 'si']), set(), False),
+    'seL4_BenchmarkResetLog':  ('BenchmarkResetLog', set(), set(), False),
+    'seL4_BenchmarkDumpLog':   ('BenchmarkDumpLog', set(['bx', 'si']), set(['bx']), False),
+    'seL4_BenchmarkLogSize':   ('BenchmarkLogSize', set(), set(['bx']), False),
+}
+
+ABIS = {
+    'x86': (X86_ABI, X86_SYSCALL_REG, X86_INFO_REG, 'sysenter'),
+}
+
+def strip_comments(src):
+    src = re.sub(r'/\*.*?\*/', '', src, flags=re.S)
+    return re.sub(r'//[^\n]*', '', src)
+
+def matching(src, start, open_ch, close_ch):
+    """Index of the bracket closing the one at src[start], skipping strings."""
+    depth = 0
+    i = start
+    in_str = False
+    while i < len(src):
+        c = src[i]
+        if in_str:
+            if c == '\\':
+                i += 1
+            elif c == '"':
+                in_str = False
+        elif c == '"':
+            in_str = True
+        elif c == open_ch:
+            depth += 1
+        elif c == close_ch:
+            depth -= 1
+            if depth == 0:
+                return i
+        i += 1
+    raise ValueError('unbalanced %s' % open_ch)
+
+def split_top(text, sep):
+    """Split on sep outside strings and brackets. A ':' that is part of a
+    '::' path separator does not count."""
+    parts = []
+    depth = 0
+    in_str = False
+    cur = ''
+    i = 0
+    while i < len(text):
+        c = text[i]
+        if in_str:
+            cur += c
+            if c == '\\':
+                cur += text[i + 1]
+                i += 1
+            elif c == '"':
+                in_str = False
+        elif c == '"':
+            in_str = True
+            cur += c
+        elif c in '([{':
+            depth += 1
+            cur += c
+        elif c in ')]}':
+            depth -= 1
+            cur += c
+        elif c == sep and depth == 0 and not \
+                (sep == ':' and (text[i + 1:i + 2] == ':' or text[i - 1:i] == ':')):
+            parts.append(cur)
+            cur = ''
+        else:
+            cur += c
+        i += 1
+    parts.append(cur)
+    return parts
+
+OPERAND = re.compile(r'^\s*"(=?)\{(\w+)\}"\s*\((.*)\)\s*$', re.S)
+
+def parse_operands(section, errors, where):
+    """[(is_output, reg, expr)] for an asm! operand section."""
+    ops = []
+    if not section.strip():
+        return ops
+    for item in split_top(section, ','):
+        m = OPERAND.match(item)
+        if m is None or '"' in m.group(3):
+            errors.append('%s: malformed operand %r (missing comma?)' % (where, ' '.join(item.split())))
+            continue
+        ops.append((m.group(1) == '=', m.group(2), m.group(3).strip()))
+    return ops
+
+def functions(src):
+    """(name, body) for every public function."""
+    for m in re.finditer(r'pub unsafe fn (\w+)\s*\(', src):
+        brace = src.index('{', matching(src, m.end() - 1, '(', ')'))
+        yield m.group(1), src[brace + 1:matching(src, brace, '{', '}')]
+
+def check_stub(name, body, abi, syscall_reg, info_reg, errors):
+    syscall, inputs, outputs, returns_info = abi
+    asm = body.find('asm!(')
+    if asm < 0:
+        errors.append('%s: no asm! block' % name)
+        return
+    args = split_top(body[asm + 5:matching(body, asm + 4, '(', ')')], ':')
+    if len(args) < 3:
+        errors.append('%s: asm! has no operand lists' % name)
+        return
+    outs = parse_operands(args[1], errors, name)
+    ins = parse_operands(args[2], errors, name)
+    clobbers = args[3] if len(args) > 3 else ''
+
+    in_regs = set(reg for _, reg, _ in ins)
+    out_regs = set(reg for _, reg, _ in outs)
+    number = [expr for _, reg, expr in ins if reg == syscall_reg]
+    expected = 'SyscallId::%s as seL4_Word' % syscall
+    if not number:
+        errors.append('%s: syscall number not loaded into %%e%s' % (name, syscall_reg))
+    elif ' '.join(number[0].split()) != expected:
+        errors.append('%s: %%e%s carries %s, expected %s' % (name, syscall_reg, number[0], expected))
+
+    in_regs.discard(syscall_reg)
+    if in_regs != inputs:
+        errors.append('%s: inputs in %s, expected %s' % (name, sorted(in_regs), sorted(inputs)))
+    if out_regs != outputs:
+        errors.append('%s: outputs in %s, expected %s' % (name, sorted(out_regs), sorted(outputs)))
+    for reg in out_regs:
+        if re.search(r'"%%e%s"' % reg, clobbers):
+            errors.append('%s: %%e%s is both an output and clobbered' % (name, reg))
+
+    if returns_info:
+        info = [expr for _, reg, expr in outs if reg == info_reg]
+        tail = body.strip().split(';')[-1].strip()
+        if not info or not info[0].startswith(tail + '.'):
+            errors.append('%s: returns %r, not the MessageInfo from %%e%s' % (name, tail, info_reg))
+
+def main():
+    parser = argparse.ArgumentParser(description='Check seL4 syscall stubs against the kernel ABI')
+    parser.add_argument('-a', '--arch', required=True, choices=sorted(ABIS.keys()))
+    parser.add_argument('file', help='Rust source with the syscall stubs')
+    args = parser.parse_args()
+
+    table, syscall_reg, info_reg, trap = ABIS[args.arch]
+    src = strip_comments(open(args.file).read())
+
+    errors = []
+    seen = set()
+    for name, body in functions(src):
+        if trap not in body:
+            continue
+        seen.add(name)
+        if name not in table:
+            errors.append('%s: not in the %s ABI table' % (name, args.arch))
+            continue
+        check_stub(name, body, table[name], syscall_reg, info_reg, errors)
+    for name in sorted(set(table.keys()) - seen):
+        errors.append('%s: missing' % name)
+
+    for e in errors:
+        print(e, file=sys.stderr)
+    if errors:
+        sys.exit(1)
+    print('%s: %d stubs match the %s ABI' % (args.file, len(seen), args.arch))
+
+if __name__ == '__main__':
+    main()
@@ -31,6 +31,7 @@ common_header = """
 libsel4_header_template = \
 """/* @LICENSE(NICTA) */""" + common_header + """
 #[repr(isize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum SyscallId {
 {{py:syscall_number = -1}}
 {{for config, list in enum}}
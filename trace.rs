@@ -0,0 +1,376 @@
+/* Copyright (c) 2015 The Robigalia Project Developers
+ * Licensed under the Apache License, Version 2.0
+ * <LICENSE-APACHE or
+ * http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+ * license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+ * at your option. All files in the project carrying such
+ * notice may not be copied, modified, or distributed except
+ * according to those terms.
+ */
+
+//! A record of the most recent syscalls, for debugging IPC protocols.
+//!
+//! With the `trace` feature every syscall function records itself in a ring
+//! buffer of the last `TRACE_ENTRIES` syscalls. Each entry holds the
+//! syscall, the cap it was made on, the MessageInfo and first `TRACE_MRS`
+//! message registers sent, and what came back. The generated invocation
+//! stubs all go through `seL4_Call` or `seL4_CallWithMRs`, so they are
+//! recorded too, and for them the reply label is the error code. `dump`
+//! prints the buffer over the debug console.
+//!
+//! For replaying a run against the mock kernel (see `replay`), `export`
+//! prints the buffer in a form `replay::parse` reads back, and `stream`
+//! prints every syscall in that form as it happens, so a whole run can be
+//! captured from the serial console rather than only the last few
+//! syscalls.
+//!
+//! `seL4_DebugPutChar` is not recorded, since the printing uses it.
+//!
+//! The buffer is shared by the threads of an address space without locking,
+//! so syscalls made at the same moment on different threads can overwrite
+//! each other's entries. Under `mock` each host thread has its own buffer,
+//! as each test has its own kernel.
+//!
+//! Without the feature the hooks in the syscall functions expand to nothing,
+//! and only the entry types are defined.
+
+use core::{cmp, fmt};
+#[cfg(all(feature = "trace", feature = "mock"))]
+use std::cell::RefCell;
+use super::*;
+
+/* Hooks for the syscall functions in arch/. `trace_start!` records the
+ * message about to be sent and evaluates to a handle for `trace_finish!`,
+ * which adds what came back: nothing, a message (its MessageInfo, badge
+ * and first two MRs, the rest being read from the IPC buffer), or a
+ * word. */
+
+#[cfg(feature = "trace")]
+macro_rules! trace_start {
+    ($syscall:ident, $dest:expr) => {
+        ::trace::start(SyscallId::$syscall, $dest, &seL4_MessageInfo::new(0, 0, 0, 0), 0, 0)
+    };
+    ($syscall:ident, $dest:expr, $info:expr, $mr0:expr, $mr1:expr) => {
+        ::trace::start(SyscallId::$syscall, $dest, &$info, $mr0, $mr1)
+    };
+}
+
+#[cfg(feature = "trace")]
+macro_rules! trace_finish {
+    ($seq:expr) => {
+        ::trace::finish($seq, ::trace::Outcome::Done)
+    };
+    ($seq:expr, $info:expr, $badge:expr, $mr0:expr, $mr1:expr) => {
+        ::trace::finish($seq, ::trace::Outcome::received(&$info, $badge, $mr0, $mr1))
+    };
+    ($seq:expr, $value:expr) => {
+        ::trace::finish($seq, ::trace::Outcome::Value($value as seL4_Word))
+    };
+}
+
+#[cfg(not(feature = "trace"))]
+macro_rules! trace_start {
+    ($($arg:tt)*) => { 0usize }
+}
+
+#[cfg(not(feature = "trace"))]
+macro_rules! trace_finish {
+    ($seq:expr) => { { let _ = $seq; } };
+    ($seq:expr, $($arg:tt)*) => { { let _ = $seq; } };
+}
+
+/// Number of syscalls the buffer remembers.
+pub const TRACE_ENTRIES: usize = 64;
+
+/// Number of message registers recorded for each message.
+pub const TRACE_MRS: usize = 4;
+
+/// The fields of a `seL4_MessageInfo`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Info {
+    pub label: seL4_Word,
+    pub caps_unwrapped: seL4_Word,
+    pub extra_caps: seL4_Word,
+    pub length: seL4_Word,
+}
+
+impl Info {
+    pub fn decode(info: &seL4_MessageInfo) -> Info {
+        Info {
+            label: info.get_label(),
+            caps_unwrapped: info.get_capsUnwrapped(),
+            extra_caps: info.get_extraCaps(),
+            length: info.get_length(),
+        }
+    }
+
+    pub fn encode(&self) -> seL4_MessageInfo {
+        seL4_MessageInfo::new(self.label, self.caps_unwrapped, self.extra_caps, self.length)
+    }
+}
+
+/* The first TRACE_MRS message registers of a message of `length` words,
+ * given the first two, which may be in registers rather than the IPC
+ * buffer. */
+unsafe fn first_mrs(length: seL4_Word, mr0: seL4_Word, mr1: seL4_Word) -> [seL4_Word; TRACE_MRS] {
+    let mut mrs = [0; TRACE_MRS];
+    for i in 0..cmp::min(length as usize, TRACE_MRS) {
+        mrs[i] = match i {
+            0 => mr0,
+            1 => mr1,
+            _ => seL4_GetMR(i as isize),
+        };
+    }
+    mrs
+}
+
+/// What a syscall returned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Nothing: a send, or a syscall with no result.
+    Done,
+    /// A received message: its MessageInfo, badge and first message
+    /// registers.
+    Reply(Info, seL4_Word, [seL4_Word; TRACE_MRS]),
+    /// A single word, from the debug and benchmark syscalls.
+    Value(seL4_Word),
+}
+
+impl Outcome {
+    #[doc(hidden)]
+    pub unsafe fn received(info: &seL4_MessageInfo, badge: seL4_Word,
+                           mr0: seL4_Word, mr1: seL4_Word) -> Outcome {
+        let info = Info::decode(info);
+        Outcome::Reply(info, badge, first_mrs(info.length, mr0, mr1))
+    }
+}
+
+/// One traced syscall.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// Position among all syscalls traced, counting from 1.
+    pub seq: usize,
+    pub syscall: SyscallId,
+    /// The cap invoked or waited on; 0 for syscalls that take none.
+    pub dest: seL4_CPtr,
+    pub info: Info,
+    /// The first message registers sent. Those past `info.length` are 0.
+    pub mrs: [seL4_Word; TRACE_MRS],
+    /// `None` until the syscall returns.
+    pub outcome: Option<Outcome>,
+}
+
+impl Entry {
+    /// An entry for a syscall about to be made, numbered 0. MRs from the
+    /// third on are read from the IPC buffer.
+    pub unsafe fn sent(syscall: SyscallId, dest: seL4_CPtr, info: &seL4_MessageInfo,
+                       mr0: seL4_Word, mr1: seL4_Word) -> Entry {
+        let info = Info::decode(info);
+        Entry {
+            seq: 0,
+            syscall: syscall,
+            dest: dest,
+            info: info,
+            mrs: first_mrs(info.length, mr0, mr1),
+            outcome: None,
+        }
+    }
+
+    /// Whether `other` is the same syscall with the same message, whatever
+    /// its position and outcome.
+    pub fn same_call(&self, other: &Entry) -> bool {
+        self.syscall == other.syscall && self.dest == other.dest &&
+            self.info == other.info && self.mrs == other.mrs
+    }
+
+    /// The entry in the line format of `export`.
+    pub fn export(&self) -> Export {
+        Export(*self)
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{:>6} {:?} cap {:#x} label {}", self.seq, self.syscall, self.dest, self.info.label));
+        if self.info.label != 0 {
+            if let Some(name) = InvocationLabel::name(self.info.label) {
+                try!(write!(f, " ({})", name));
+            }
+        }
+        try!(write!(f, " caps {} len {}", self.info.extra_caps, self.info.length));
+        for mr in &self.mrs[..cmp::min(self.info.length as usize, TRACE_MRS)] {
+            try!(write!(f, " {:#x}", mr));
+        }
+        match self.outcome {
+            None => f.write_str(" -> (not returned)"),
+            Some(Outcome::Done) => Ok(()),
+            Some(Outcome::Reply(info, badge, _)) => {
+                write!(f, " -> label {} len {} badge {:#x}", info.label, info.length, badge)
+            }
+            Some(Outcome::Value(value)) => write!(f, " -> {:#x}", value),
+        }
+    }
+}
+
+/// Prefix of the lines written by `export` and `stream`.
+pub const EXPORT_PREFIX: &'static str = "sel4-trace";
+
+/// An entry formatted as one line of `export` output:
+///
+/// ```text
+/// sel4-trace SEQ SYSCALL DEST LABEL UNWRAPPED EXTRA LENGTH MR0 MR1 MR2 MR3 OUTCOME
+/// ```
+///
+/// where `OUTCOME` is `-` if the syscall has not returned, `done`,
+/// `value WORD`, or `reply LABEL UNWRAPPED EXTRA LENGTH BADGE MR0 MR1 MR2
+/// MR3`. `SEQ` is decimal, `SYSCALL` a `SyscallId` name, and everything
+/// else hexadecimal.
+pub struct Export(Entry);
+
+impl fmt::Display for Export {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn message(f: &mut fmt::Formatter, info: &Info, mrs: &[seL4_Word]) -> fmt::Result {
+            try!(write!(f, " {:x} {:x} {:x} {:x}", info.label, info.caps_unwrapped, info.extra_caps, info.length));
+            for mr in mrs {
+                try!(write!(f, " {:x}", mr));
+            }
+            Ok(())
+        }
+
+        let e = &self.0;
+        try!(write!(f, "{} {} {:?} {:x}", EXPORT_PREFIX, e.seq, e.syscall, e.dest));
+        try!(message(f, &e.info, &e.mrs));
+        match e.outcome {
+            None => f.write_str(" -"),
+            Some(Outcome::Done) => f.write_str(" done"),
+            Some(Outcome::Value(value)) => write!(f, " value {:x}", value),
+            Some(Outcome::Reply(ref info, badge, ref mrs)) => {
+                try!(f.write_str(" reply"));
+                try!(message(f, info, &[]));
+                try!(write!(f, " {:x}", badge));
+                for mr in mrs {
+                    try!(write!(f, " {:x}", mr));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "trace")]
+struct Ring {
+    /* Sequence number of the newest entry, which is in entries[next % len]. */
+    next: usize,
+    entries: [Option<Entry>; TRACE_ENTRIES],
+    streaming: bool,
+}
+
+#[cfg(all(feature = "trace", not(feature = "mock")))]
+static mut RING: Ring = Ring { next: 0, entries: [None; TRACE_ENTRIES], streaming: false };
+
+#[cfg(all(feature = "trace", not(feature = "mock")))]
+fn with_ring<R, F: FnOnce(&mut Ring) -> R>(f: F) -> R {
+    unsafe { f(&mut RING) }
+}
+
+#[cfg(all(feature = "trace", feature = "mock"))]
+thread_local!(static RING: RefCell<Ring> =
+              RefCell::new(Ring { next: 0, entries: [None; TRACE_ENTRIES], streaming: false }));
+
+#[cfg(all(feature = "trace", feature = "mock"))]
+fn with_ring<R, F: FnOnce(&mut Ring) -> R>(f: F) -> R {
+    RING.with(|ring| f(&mut ring.borrow_mut()))
+}
+
+#[cfg(all(feature = "trace", feature = "SEL4_DEBUG"))]
+fn print_streamed(entry: Option<Entry>) {
+    if let Some(entry) = entry {
+        debug_println!("{}", entry.export());
+    }
+}
+
+#[cfg(all(feature = "trace", not(feature = "SEL4_DEBUG")))]
+fn print_streamed(_: Option<Entry>) {
+}
+
+#[doc(hidden)]
+#[cfg(feature = "trace")]
+pub unsafe fn start(syscall: SyscallId, dest: seL4_CPtr, info: &seL4_MessageInfo,
+                    mr0: seL4_Word, mr1: seL4_Word) -> usize {
+    let mut entry = Entry::sent(syscall, dest, info, mr0, mr1);
+    let (seq, streamed) = with_ring(|ring| {
+        ring.next += 1;
+        entry.seq = ring.next;
+        ring.entries[ring.next % TRACE_ENTRIES] = Some(entry);
+        (ring.next, if ring.streaming { Some(entry) } else { None })
+    });
+    print_streamed(streamed);
+    seq
+}
+
+#[doc(hidden)]
+#[cfg(feature = "trace")]
+pub fn finish(seq: usize, outcome: Outcome) {
+    let streamed = with_ring(|ring| {
+        let streaming = ring.streaming;
+        match ring.entries[seq % TRACE_ENTRIES] {
+            Some(ref mut entry) if entry.seq == seq => {
+                entry.outcome = Some(outcome);
+                if streaming { Some(*entry) } else { None }
+            }
+            _ => None,
+        }
+    });
+    print_streamed(streamed);
+}
+
+/// A copy of the buffer, oldest entry first. Unused slots are `None` and
+/// come last.
+#[cfg(feature = "trace")]
+pub fn entries() -> [Option<Entry>; TRACE_ENTRIES] {
+    with_ring(|ring| {
+        let mut out = [None; TRACE_ENTRIES];
+        let first = if ring.next < TRACE_ENTRIES { 1 } else { ring.next - TRACE_ENTRIES + 1 };
+        let mut n = 0;
+        for seq in first..ring.next + 1 {
+            if let Some(entry) = ring.entries[seq % TRACE_ENTRIES] {
+                out[n] = Some(entry);
+                n += 1;
+            }
+        }
+        out
+    })
+}
+
+/// Forget everything recorded so far. Sequence numbers keep counting.
+#[cfg(feature = "trace")]
+pub fn clear() {
+    with_ring(|ring| ring.entries = [None; TRACE_ENTRIES])
+}
+
+/// Print the buffer on the kernel debug console, oldest entry first.
+#[cfg(all(feature = "trace", feature = "SEL4_DEBUG"))]
+pub fn dump() {
+    let entries = entries();
+    debug_println!("syscall trace, oldest first:");
+    for entry in entries.iter().filter_map(|e| e.as_ref()) {
+        debug_println!("{}", entry);
+    }
+}
+
+/// Print the buffer on the kernel debug console in the line format of
+/// `Export`, oldest entry first.
+#[cfg(all(feature = "trace", feature = "SEL4_DEBUG"))]
+pub fn export() {
+    for entry in entries().iter().filter_map(|e| e.as_ref()) {
+        debug_println!("{}", entry.export());
+    }
+}
+
+/// Print every syscall from now on as it is made and again when it
+/// returns, in the line format of `Export`. Later lines for a sequence
+/// number replace earlier ones.
+#[cfg(all(feature = "trace", feature = "SEL4_DEBUG"))]
+pub fn stream(on: bool) {
+    with_ring(|ring| ring.streaming = on)
+}